@@ -0,0 +1,75 @@
+export fzs_name="{{ fzs_name }}"
+export FZS_ROOT_DIR="{{ fzs_root_dir }}"
+export FZS_PATH_DIR="{{ fzs_path_dir }}"
+export FZS_DATA_DIR="{{ fzs_data_dir }}"
+export FZS_CONFIG_DIR="{{ fzs_config_dir }}"
+export fzs_provides_file="{{ fzs_provides_file }}"
+export fzs_fzf_dir_cmd="{{ fzs_fzf_dir_cmd }}"
+export fzs_fzf_pager_cmd="{{ fzs_fzf_pager_cmd }}"
+export fzs_init_file="{{ fzs_init_file }}"
+export fzs_plugins_file="{{ fzs_plugins_file }}"
+
+# bash cannot name functions after a variable, so these use the fzs_name of the build
+# widgets are bound with bind -x, which sets READLINE_LINE and READLINE_POINT and redraws the line afterwards
+
+{{ fzs_name }}._insert () {
+  READLINE_LINE="${READLINE_LINE:0:READLINE_POINT}$1${READLINE_LINE:READLINE_POINT}"
+  {% raw %}READLINE_POINT=$((READLINE_POINT + ${#1})){% endraw %}
+}
+
+# appends a selection to the history fzs ranks actions by, see `fzs rank`
+{{ fzs_name }}._record () {
+  printf '%(%s)T\t%s\t%s\n' -1 "$1" "$2" >> "$FZS_DATA_DIR/history.tsv"
+}
+
+{{ fzs_name }}._in () {
+  local d="${3:-,}"
+  [[ "$d$1$d" == *"$d$2$d"* ]];
+}
+
+{{ fzs_name }}._fzf.wg () {
+    fzf \
+      --ansi \
+      --delimiter '\t' \
+      --with-nth '1,4..' \
+      --preview '{{ fzs_fzf_base_preview }}' \
+      --layout=reverse \
+      --height=70% \
+      "${@}"
+}
+
+# the selector of template_file, with the default accept arguments if it does not pass accept_args
+{{ fzs_name }}._base-select.wg () {
+  "{{ fzs_name }}"._fzf.wg {{ base_accept_args }} "${@}"
+}
+
+{{ fzs_name }}.plugin-select.wg () {
+  local fn_table="{{ fn_table }}"
+  local selected
+  selected=$(
+    "{{ fzs_name }}"._fzf.wg \
+     --preview "echo {2}; {{ fzs_fzf_dir_cmd }} {2}" \
+     {{ plugin_accept_args }} \
+    <<< "$fn_table"
+  )
+  [[ -z "$selected" ]] && return
+
+  local alias dir cmd rest
+  IFS=$'\t' read -r alias dir cmd rest <<<"$selected"
+  "$cmd"
+}
+
+{{ fzs_name }}.all-fn-select.wg () {
+  local fn_table="{{ all_fn_table }}"
+  local selected
+  selected=$(
+    "{{ fzs_name }}"._fzf.wg {{ all_fn_accept_args }} <<< "$fn_table"
+  )
+  [[ -z "$selected" ]] && return
+
+  local pg_alias cmd rest
+  IFS=$'\t' read -r pg_alias cmd rest <<<"$selected"
+  "{{ fzs_name }}"._record "*" "$cmd"
+
+  "{{ fzs_name }}"._insert "$cmd "
+}
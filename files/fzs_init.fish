@@ -0,0 +1,80 @@
+set -gx fzs_name "{{ fzs_name }}"
+set -gx FZS_ROOT_DIR "{{ fzs_root_dir }}"
+set -gx FZS_PATH_DIR "{{ fzs_path_dir }}"
+set -gx FZS_DATA_DIR "{{ fzs_data_dir }}"
+set -gx FZS_CONFIG_DIR "{{ fzs_config_dir }}"
+set -gx fzs_provides_file "{{ fzs_provides_file }}"
+set -gx fzs_fzf_dir_cmd "{{ fzs_fzf_dir_cmd }}"
+set -gx fzs_fzf_pager_cmd "{{ fzs_fzf_pager_cmd }}"
+set -gx fzs_init_file "{{ fzs_init_file }}"
+set -gx fzs_plugins_file "{{ fzs_plugins_file }}"
+
+# fish cannot name functions after a variable, so these use the fzs_name of the build
+# binds call their function with --widget, see the widgets in the generated plugin files
+
+function {{ fzs_name }}._cleanup-prompt.wg
+  set -q argv[1]; and commandline -r -- $argv[1]
+  set -q argv[2]; and commandline -C -- $argv[2]
+  commandline -f repaint
+end
+
+# appends a selection to the history fzs ranks actions by, see `fzs rank`
+function {{ fzs_name }}._record
+  printf '%s\t%s\t%s\n' (date +%s) $argv[1] $argv[2] >> "$FZS_DATA_DIR/history.tsv"
+end
+
+function {{ fzs_name }}._in
+  set -l d ,
+  set -q argv[3]; and set d $argv[3]
+  string match -q -- "*$d$argv[2]$d*" "$d$argv[1]$d"
+end
+
+function {{ fzs_name }}._fzf.wg
+    fzf \
+      --ansi \
+      --delimiter '\t' \
+      --with-nth '1,4..' \
+      --preview '{{ fzs_fzf_base_preview }}' \
+      --layout=reverse \
+      --height=70% \
+      $argv
+end
+
+# the selector of template_file, with the default accept arguments if it does not pass accept_args
+function {{ fzs_name }}._base-select.wg
+  {{ fzs_name }}._fzf.wg {{ base_accept_args }} $argv
+end
+
+function {{ fzs_name }}.plugin-select.wg
+  set -l fn_table "{{ fn_table }}"
+  set -l selected (
+    printf '%s\n' $fn_table | {{ fzs_name }}._fzf.wg \
+     --preview "echo {2}; {{ fzs_fzf_dir_cmd }} {2}" \
+     {{ plugin_accept_args }}
+  )
+  if test -z "$selected"
+    {{ fzs_name }}._cleanup-prompt.wg
+    return
+  end
+  commandline -f repaint
+
+  set -l fields (string split \t -- $selected)
+  $fields[3] --widget
+end
+
+function {{ fzs_name }}.all-fn-select.wg
+  set -l fn_table "{{ all_fn_table }}"
+  set -l selected (
+    printf '%s\n' $fn_table | {{ fzs_name }}._fzf.wg {{ all_fn_accept_args }}
+  )
+  if test -z "$selected"
+    {{ fzs_name }}._cleanup-prompt.wg
+    return
+  end
+  commandline -f repaint
+
+  set -l fields (string split \t -- $selected)
+  # unlike read, string split keeps the empty second column
+  {{ fzs_name }}._record "*" $fields[3]
+  commandline -i -- "$fields[3] "
+end
@@ -0,0 +1,12 @@
+
+### STALE CHECK
+# true when config.toml, a directory or a source under FZS_ROOT_DIR is newer than the last build
+{{ fzs_name }}._stale () {
+  local stamp="{{ stamp_file }}"
+  [[ "{{ config_file }}" -nt $stamp ]] && return 0
+  [[ "$FZS_ROOT_DIR" -nt $stamp ]] && return 0
+  [[ -n $(find "$FZS_ROOT_DIR" \( -type d -o {{ source_pattern }} \) -newer "$stamp" -print -quit 2>/dev/null) ]]
+}
+if [[ -z $_fzs_rebuilt ]] && {{ fzs_name }}._stale; then
+{{ on_stale }}
+fi
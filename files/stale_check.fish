@@ -0,0 +1,12 @@
+
+### STALE CHECK
+# true when config.toml, a directory or a source under FZS_ROOT_DIR is newer than the last build
+function {{ fzs_name }}._stale
+  set -l stamp "{{ stamp_file }}"
+  test -e "$stamp"; or return 0
+  set -l changed (find "{{ config_file }}" "$FZS_ROOT_DIR" \( -type d -o {{ source_pattern }} -o -path "{{ config_file }}" \) -newer "$stamp" -print -quit 2>/dev/null)
+  test -n "$changed"
+end
+if not set -q _fzs_rebuilt; and {{ fzs_name }}._stale
+{{ on_stale }}
+end
@@ -0,0 +1,26 @@
+
+{{ selector_name }} () {
+  local INIT_LINE="$READLINE_LINE" INIT_POINT="$READLINE_POINT"
+  local fn_table="{{ fn_table }}"
+  local selected
+  selected=$(
+    "{{ fzs_name }}"._base-select.wg {{ accept_args }} <<< "$fn_table"
+  )
+  [[ -z "$selected" ]] && return
+
+  local name flags cmd desc
+  IFS=$'\t' read -r name flags cmd desc <<<"$selected"
+  "{{ fzs_name }}"._record "{{ pg_name }}" "$cmd"
+
+  case ",$flags," in
+    *",PL,"*) "$cmd"; return $? ;;
+    *",NR,"*) "{{ fzs_name }}"._insert "$cmd "; return ;;
+    *",W"*) "$cmd" ;;
+    *",SS,"*) ( eval "$cmd" ) ;;
+    *) eval "$cmd" ;;
+  esac
+
+  case ",$flags," in
+    *",RP,"*) READLINE_LINE="$INIT_LINE"; READLINE_POINT="$INIT_POINT" ;;
+  esac
+}
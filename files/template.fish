@@ -0,0 +1,44 @@
+
+function {{ selector_name }}
+  set -l INIT_BUFFER (commandline | string collect)
+  set -l pg_name "{{ pg_name }}"
+  set -l fn_table "{{ fn_table }}"
+  set -l selected (
+    printf '%s\n' $fn_table | {{ fzs_name }}._base-select.wg {{ accept_args }}
+  )
+  if test -z "$selected"
+    {{ fzs_name }}._cleanup-prompt.wg
+    return
+  end
+  commandline -f repaint
+
+  set -l fields (string split \t -- $selected)
+  set -l flags $fields[2]
+  set -l cmd $fields[3]
+  {{ fzs_name }}._record $pg_name $cmd
+
+  switch ",$flags,"
+    case "*,PL,*"
+      $cmd --widget
+      return $status
+    case "*,NR,*"
+      commandline -i -- "$cmd "
+      return
+    case "*,W*"
+      $cmd --widget
+    case "*,SS,*"
+      fish -c "$cmd"
+    case "*"
+      eval $cmd
+  end
+
+  switch ",$flags,"
+    case "*,RP,*"
+      {{ fzs_name }}._cleanup-prompt.wg "$INIT_BUFFER"
+      return $status
+    case "*,NC,*"
+    case "*"
+      {{ fzs_name }}._cleanup-prompt.wg
+      return $status
+  end
+end
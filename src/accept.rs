@@ -0,0 +1,194 @@
+use crate::backend::Backend;
+use crate::quote;
+use regex::Regex;
+use serde::Deserialize;
+
+// When a selector accepts the focused row without pressing enter
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    #[default]
+    Prefix, // the query starts as ^ so it matches from the start, and the last row left is accepted
+    Fuzzy, // never, the query is a plain fuzzy search
+    Alias, // once the query equals the alias of the focused row
+    Mnemonic, // once the query equals an alias that no other row's alias starts with
+}
+
+// The [settings.accept] or [plugins.accept] table, a plugin's overrides the global one key by key
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RawAccept {
+    strategy: Option<Strategy>,
+    keys: Option<Vec<String>>,
+    space: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Accept {
+    pub strategy: Strategy,
+    pub keys: Vec<String>, // fzf keys that accept besides enter, i.e. tab or ctrl-o
+    pub space: bool,
+}
+
+impl RawAccept {
+    pub fn into_accept(self) -> Accept {
+        Accept {
+            strategy: self.strategy.unwrap_or_default(),
+            keys: self.keys.unwrap_or_default(),
+            space: self.space.unwrap_or(true),
+        }
+    }
+
+    pub fn merge(&mut self, other: RawAccept) {
+        if other.strategy.is_some() {
+            self.strategy = other.strategy;
+        }
+        if other.keys.is_some() {
+            self.keys = other.keys;
+        }
+        if other.space.is_some() {
+            self.space = other.space;
+        }
+    }
+}
+
+impl Accept {
+    pub fn with(&self, raw: &RawAccept) -> Accept {
+        Accept {
+            strategy: raw.strategy.unwrap_or(self.strategy),
+            keys: raw.keys.clone().unwrap_or_else(|| self.keys.clone()),
+            space: raw.space.unwrap_or(self.space),
+        }
+    }
+
+    // The fzf arguments a selector passes to _base-select.wg, quoted for backend. field is the column of table holding the
+    // aliases, counting from 1 like fzf's {1}, see alias_field. The transform commands are run by fzf with $SHELL, so they hand the
+    // comparison to sh to work the same under fish.
+    pub fn fzf_args(&self, table: &str, field: usize, backend: &dyn Backend) -> String {
+        let mut args: Vec<String> = Vec::new();
+        if self.space {
+            args.extend(["--bind".to_string(), "space:accept".to_string()]);
+        }
+        for key in &self.keys {
+            args.extend(["--bind".to_string(), format!("{}:accept", key)]);
+        }
+        match self.strategy {
+            Strategy::Prefix => args.extend([
+                "--bind".to_string(),
+                transform("one", r#"[ "$1" != ^ ] && echo accept"#, "{q}"),
+                "--bind".to_string(),
+                r#"zero:transform-query(printf "%s" "$FZF_QUERY" | sed "s/^\^//")"#.to_string(),
+                "--query".to_string(),
+                "^".to_string(),
+            ]),
+            Strategy::Fuzzy => {}
+            Strategy::Alias => args.extend([
+                "--bind".to_string(),
+                transform(
+                    "result",
+                    r#"[ -n "$1" ] && [ "$1" = "$2" ] && echo accept"#,
+                    &format!("{{q}} {{{}}}", field),
+                ),
+            ]),
+            Strategy::Mnemonic => {
+                let unique = unique_mnemonics(table, field);
+                if !unique.is_empty() {
+                    let patterns = unique.iter().map(|m| quote::posix_word(m)).collect::<Vec<String>>();
+                    let script = format!(
+                        r#"case "$1" in {}) [ "$1" = "$2" ] && echo accept ;; esac"#,
+                        patterns.join("|")
+                    );
+                    args.extend([
+                        "--bind".to_string(),
+                        transform("result", &script, &format!("{{q}} {{{}}}", field)),
+                    ]);
+                }
+            }
+        }
+        args.iter().map(|arg| backend.word(arg)).collect::<Vec<String>>().join(" ")
+    }
+}
+
+// The column of the rows of table_template that shows the alias, counting from 1, or the name's if none does
+pub fn alias_field(table_template: &str) -> usize {
+    let field_of = |var: &str| {
+        let re = Regex::new(&format!(r"\{{\{{-?\s*{}\b", var)).unwrap();
+        table_template.split('\t').position(|column| re.is_match(column)).map(|index| index + 1)
+    };
+    field_of("alias").or_else(|| field_of("name")).unwrap_or(1)
+}
+
+pub fn transform(event: &str, script: &str, placeholders: &str) -> String {
+    format!("{}:transform:sh -c {} _ {}", event, quote::posix_word(script), placeholders)
+}
+
+// The text of a table cell as fzf's {n} sees it, without the padding and colours of table::render
+fn cell(line: &str, field: usize) -> String {
+    let raw = line.split('\t').nth(field.saturating_sub(1)).unwrap_or("");
+    let mut text = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            text.push(c);
+        }
+    }
+    text.trim().to_string()
+}
+
+// Mnemonics that neither repeat nor start another one, so typing them fully cannot be meant for a different row
+fn unique_mnemonics(table: &str, field: usize) -> Vec<String> {
+    let mnemonics: Vec<String> = table
+        .lines()
+        .map(|line| cell(line, field))
+        .filter(|m| !m.is_empty())
+        .collect();
+    let mut unique: Vec<String> = mnemonics
+        .iter()
+        .filter(|m| mnemonics.iter().filter(|other| other.starts_with(m.as_str())).count() == 1)
+        .cloned()
+        .collect();
+    unique.sort();
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Shell;
+    use crate::template;
+
+    // A row of table_template as templatize renders it
+    fn row(table_template: &str, name: &str, alias: &str) -> String {
+        minijinja::Environment::new()
+            .render_str(
+                table_template,
+                minijinja::context! {
+                    pg_name => "files", pg_alias => "f", name, alias, desc => "a description",
+                    flags => ",WG,", cmds => format!("f.{}", name), key => "",
+                },
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn alias_field_of_the_default_tables() {
+        for table_template in [template::FN_TABLE_TEMPLATE, template::ALL_FN_TABLE_TEMPLATE] {
+            let field = alias_field(table_template);
+            assert_eq!(cell(&row(table_template, "open-file", "of"), field), "of", "{}", table_template);
+        }
+        assert_eq!(alias_field("{{ pg_alias }}\t{{ cmds }}\t{{ name | upper }}"), 3);
+    }
+
+    #[test]
+    fn alias_strategies_compare_the_alias() {
+        let table_template = template::FN_TABLE_TEMPLATE;
+        let field = alias_field(table_template);
+        let table = [row(table_template, "open-file", "of"), row(table_template, "open-folder", "o")].join("\n");
+        let backend = Shell::Zsh.backend();
+        let accept = |strategy| Accept { strategy, keys: vec![], space: false }.fzf_args(&table, field, backend);
+        assert!(accept(Strategy::Alias).contains(&format!("{{q}} {{{}}}", field)));
+        assert_eq!(unique_mnemonics(&table, field), ["of"]);
+        assert!(accept(Strategy::Mnemonic).contains("in of)"));
+    }
+}
@@ -0,0 +1,484 @@
+use crate::completion::{self, Group};
+use crate::keybind::{self, Keymap};
+use crate::quote;
+use crate::{compile_to_zwc, FnFlag, StaleCheck};
+use serde::Deserialize;
+use std::io;
+use std::path::Path;
+
+// The shell fzs generates code for, set with `shell` in [settings]
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    #[default]
+    Zsh,
+    Bash,
+    Fish,
+}
+
+impl Shell {
+    pub fn backend(self) -> &'static dyn Backend {
+        match self {
+            Shell::Zsh => &Zsh,
+            Shell::Bash => &Bash,
+            Shell::Fish => &Fish,
+        }
+    }
+}
+
+// Everything shell specific in the generated files. The tables fed to the selectors are shared, see build_plugin_table and build_all_fn_table.
+pub trait Backend {
+    // of the generated files, and of the default template_file
+    fn extension(&self) -> &'static str;
+    fn rc_file(&self) -> &'static str;
+    // mirror files/fzs_init.zsh and files/template.zsh
+    fn init_template(&self) -> &'static str;
+    fn selector_template(&self) -> &'static str;
+    fn stale_check_template(&self) -> &'static str;
+
+    fn alias(&self, alias: &str, cmd: &str) -> String;
+    // what every line written by alias starts with, see Plan
+    fn alias_prefix(&self) -> &'static str {
+        "alias "
+    }
+    // PFN
+    fn function(&self, name: &str, cmd: &str) -> String;
+    // PBG
+    fn background(&self, name: &str, cmd: &str) -> String;
+    // WJR, WJSUB, WR and WSUB: a function named name running cmd, usable as a widget
+    fn widget(&self, flag: &FnFlag, name: &str, cmd: &str) -> Vec<String>;
+    // key is in zsh's caret notation, see keybind::translate
+    fn bind(&self, key: &str, widget: &str, keymap: Option<Keymap>) -> String;
+    // what every line written by bind starts with, see Plan
+    fn bind_prefix(&self) -> &'static str;
+    // the emacs keymap's binds a bind would shadow, see keybind::Registry::check
+    fn default_binds(&self) -> &'static [(&'static str, &'static str)];
+    // sources a plugin's files with its env (this, this_name and the fstrings) set
+    fn source(&self, env: &[(String, String)], sources: &[String]) -> String;
+    // `this` prints the alias of the plugin of the calling function
+    fn this_fn(&self) -> &'static str;
+    fn on_stale(&self, stale_check: StaleCheck, fzs_name: &str, rebuild_cmd: &str) -> String;
+
+    // Matches the sources by their extensions in the stale check, find tests by default
+    fn source_pattern(&self, extensions: &[String]) -> String {
+        extensions
+            .iter()
+            .map(|extension| format!("-name {}", self.word(&format!("*.{}", extension))))
+            .collect::<Vec<String>>()
+            .join(" -o ")
+    }
+
+    fn compiles(&self) -> bool {
+        false
+    }
+    fn compile(&self, _file_path: &Path) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    // The completion file sourced by the init file, None if the shell has no generated completion
+    // todo: complete -F for bash, complete -c for fish
+    fn completion(&self, _groups: &[Group], _fzs_name: &str) -> Option<String> {
+        None
+    }
+
+    // Escaping for the contexts values end up in, see quote. cmds passed to the methods above are code and are only escaped
+    // where they are embedded into a string.
+    fn double_quoted(&self, s: &str) -> String {
+        quote::posix_double(s)
+    }
+    fn single_quoted(&self, s: &str) -> String {
+        quote::posix_single(s)
+    }
+    fn word(&self, s: &str) -> String {
+        quote::posix_word(s)
+    }
+    // like double_quoted, but $HOME, $FZS_ROOT_DIR and $FZS_PATH_DIR still expand
+    fn double_quoted_path(&self, s: &str) -> String {
+        quote::keeping_path_vars(s, |part| self.double_quoted(part))
+    }
+}
+
+// zsh and bash both take env assignments in front of source
+fn posix_source(env: &[(String, String)], sources: &[String]) -> String {
+    let env: String = env
+        .iter()
+        .map(|(key, val)| format!("{}={} \\\n", key, quote::posix_word(val)))
+        .collect();
+    // todo: unsure if joining with cat is slower than calling source multiple times
+    if sources.len() > 1 {
+        format!("\n{}source <(cat {})\n", env, sources.join(" "))
+    } else {
+        format!("\n{}source {}\n", env, sources.join(" "))
+    }
+}
+
+pub struct Zsh;
+
+impl Backend for Zsh {
+    fn extension(&self) -> &'static str {
+        "zsh"
+    }
+    fn rc_file(&self) -> &'static str {
+        "~/.zshrc"
+    }
+    fn init_template(&self) -> &'static str {
+        include_str!("../files/fzs_init.zsh")
+    }
+    fn selector_template(&self) -> &'static str {
+        include_str!("../files/template.zsh")
+    }
+    fn stale_check_template(&self) -> &'static str {
+        include_str!("../files/stale_check.zsh")
+    }
+
+    fn alias(&self, alias: &str, cmd: &str) -> String {
+        format!("{}{}=\"{}\"", self.alias_prefix(), self.word(alias), self.double_quoted(cmd))
+    }
+    fn function(&self, name: &str, cmd: &str) -> String {
+        format!("{}() {{ {} $@; }}", name, cmd)
+    }
+    fn background(&self, name: &str, cmd: &str) -> String {
+        format!(
+            "{}() {{ pueue add  --escape -- {} $@ >/dev/null 2>&1; }}",
+            name, cmd
+        )
+    }
+    fn widget(&self, flag: &FnFlag, name: &str, cmd: &str) -> Vec<String> {
+        let function = match flag {
+            FnFlag::WJR => format!(
+                "{}() {{ if zle; then local INIT_BUFFER=$BUFFER; local INIT_CURSOR=$CURSOR; {}; BUFFER=$INIT_BUFFER; CURSOR=$INIT_CURSOR; zle redisplay; else {} $@; fi; }}",
+                name, cmd, cmd
+            ),
+            FnFlag::WJSUB => format!(
+                "{}() {{ if zle; then LBUFFER+=\"$({} | tr '\n' ' \\\n') \"; else {} $@; fi }}",
+                name, cmd, cmd
+            ),
+            // temporarily run a command
+            FnFlag::WR => format!(
+                "{}() {{ if zle; then zle push-input; BUFFER=\"{} \"; else {} $@; fi; }}",
+                name,
+                self.double_quoted(cmd),
+                cmd
+            ),
+            FnFlag::WSUB => format!(
+                "{}() {{ if zle; then local wgArgs; vared -p \"Args: \" -c wgArgs; LBUFFER+=\"$({} ${{(z)wgArgs}}  | tr '\n' ' \\\n') \"; else {} $@; fi }}",
+                name, cmd, cmd
+            ),
+            _ => return Vec::new(),
+        };
+        vec![function, format!("zle -N {}", name)]
+    }
+    fn bind(&self, key: &str, widget: &str, keymap: Option<Keymap>) -> String {
+        let keymap = match keymap {
+            Some(Keymap::Emacs) => "-M emacs ",
+            Some(Keymap::Viins) => "-M viins ",
+            Some(Keymap::Vicmd) => "-M vicmd ",
+            None => "",
+        };
+        format!(
+            "{}{}'{}' \"{}\"",
+            self.bind_prefix(),
+            keymap,
+            self.single_quoted(key),
+            self.double_quoted(widget)
+        )
+    }
+    fn bind_prefix(&self) -> &'static str {
+        "bindkey "
+    }
+    fn default_binds(&self) -> &'static [(&'static str, &'static str)] {
+        keybind::ZSH_DEFAULTS
+    }
+    fn source(&self, env: &[(String, String)], sources: &[String]) -> String {
+        posix_source(env, sources)
+    }
+    fn this_fn(&self) -> &'static str {
+        "this(){echo ${${funcstack[2]}%%.*};}\n"
+    }
+    // a glob alternation
+    fn source_pattern(&self, extensions: &[String]) -> String {
+        format!("*.({})", extensions.join("|"))
+    }
+    fn on_stale(&self, stale_check: StaleCheck, fzs_name: &str, rebuild_cmd: &str) -> String {
+        match stale_check {
+            StaleCheck::Off => String::new(),
+            StaleCheck::Warn => format!(
+                "  print -u2 \"{}: plugins changed since the last build, run fzs build\"",
+                self.double_quoted(fzs_name)
+            ),
+            StaleCheck::Rebuild => format!(
+                "  if {}; then\n    _fzs_rebuilt=1 source \"$fzs_init_file\"\n    return\n  fi",
+                rebuild_cmd
+            ),
+        }
+    }
+
+    fn compiles(&self) -> bool {
+        true
+    }
+    fn compile(&self, file_path: &Path) -> Result<(), io::Error> {
+        compile_to_zwc(file_path)
+    }
+    fn completion(&self, groups: &[Group], fzs_name: &str) -> Option<String> {
+        Some(completion::zsh(groups, fzs_name))
+    }
+}
+
+// zsh's caret notation (^[p, ^X^P) to readline's (\ep, \C-x\C-p), other keys are passed through
+pub fn readline_key(key: &str) -> String {
+    let mut converted = String::new();
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '^' => match chars.next() {
+                Some('[') => converted.push_str("\\e"),
+                Some(next) => {
+                    converted.push_str("\\C-");
+                    converted.push(next.to_ascii_lowercase());
+                }
+                None => converted.push('^'),
+            },
+            _ => converted.push(c),
+        }
+    }
+    converted
+}
+
+pub struct Bash;
+
+// inserts the output of cmd at the cursor, like LBUFFER+= in zsh
+fn bash_insert(cmd: &str) -> String {
+    format!(
+        "local out; out=\"$({} | tr '\\n' ' ') \"; READLINE_LINE=\"${{READLINE_LINE:0:READLINE_POINT}}$out${{READLINE_LINE:READLINE_POINT}}\"; READLINE_POINT=$((READLINE_POINT + ${{#out}}))",
+        cmd
+    )
+}
+
+impl Backend for Bash {
+    fn extension(&self) -> &'static str {
+        "bash"
+    }
+    fn rc_file(&self) -> &'static str {
+        "~/.bashrc"
+    }
+    fn init_template(&self) -> &'static str {
+        include_str!("../files/fzs_init.bash")
+    }
+    fn selector_template(&self) -> &'static str {
+        include_str!("../files/template.bash")
+    }
+    fn stale_check_template(&self) -> &'static str {
+        include_str!("../files/stale_check.bash")
+    }
+
+    fn alias(&self, alias: &str, cmd: &str) -> String {
+        format!("{}{}=\"{}\"", self.alias_prefix(), self.word(alias), self.double_quoted(cmd))
+    }
+    fn function(&self, name: &str, cmd: &str) -> String {
+        format!("{}() {{ {} \"$@\"; }}", name, cmd)
+    }
+    fn background(&self, name: &str, cmd: &str) -> String {
+        format!(
+            "{}() {{ pueue add --escape -- {} \"$@\" >/dev/null 2>&1; }}",
+            name, cmd
+        )
+    }
+    // bind -x runs these with READLINE_LINE and READLINE_POINT set, and redraws the line afterwards
+    fn widget(&self, flag: &FnFlag, name: &str, cmd: &str) -> Vec<String> {
+        let in_widget = "[[ -n ${READLINE_POINT+x} ]]";
+        let function = match flag {
+            FnFlag::WJR => format!(
+                "{}() {{ if {}; then local INIT_LINE=$READLINE_LINE INIT_POINT=$READLINE_POINT; {}; READLINE_LINE=$INIT_LINE; READLINE_POINT=$INIT_POINT; else {} \"$@\"; fi; }}",
+                name, in_widget, cmd, cmd
+            ),
+            FnFlag::WJSUB => format!(
+                "{}() {{ if {}; then {}; else {} \"$@\"; fi; }}",
+                name,
+                in_widget,
+                bash_insert(cmd),
+                cmd
+            ),
+            FnFlag::WR => format!(
+                "{}() {{ if {}; then READLINE_LINE=\"{} \"; READLINE_POINT=${{#READLINE_LINE}}; else {} \"$@\"; fi; }}",
+                name,
+                in_widget,
+                self.double_quoted(cmd),
+                cmd
+            ),
+            FnFlag::WSUB => format!(
+                "{}() {{ if {}; then local wgArgs; read -e -p \"Args: \" wgArgs; {}; else {} \"$@\"; fi; }}",
+                name,
+                in_widget,
+                bash_insert(&format!("{} $wgArgs", cmd)),
+                cmd
+            ),
+            _ => return Vec::new(),
+        };
+        vec![function]
+    }
+    fn bind(&self, key: &str, widget: &str, keymap: Option<Keymap>) -> String {
+        let keymap = match keymap {
+            Some(Keymap::Emacs) => "-m emacs ",
+            Some(Keymap::Viins) => "-m vi-insert ",
+            Some(Keymap::Vicmd) => "-m vi-command ",
+            None => "",
+        };
+        format!(
+            "{}{}-x '{}'",
+            self.bind_prefix(),
+            keymap,
+            self.single_quoted(&format!("\"{}\": {}", readline_key(key), self.word(widget)))
+        )
+    }
+    fn bind_prefix(&self) -> &'static str {
+        "bind "
+    }
+    fn default_binds(&self) -> &'static [(&'static str, &'static str)] {
+        keybind::BASH_DEFAULTS
+    }
+    fn source(&self, env: &[(String, String)], sources: &[String]) -> String {
+        posix_source(env, sources)
+    }
+    fn this_fn(&self) -> &'static str {
+        "this(){ echo \"${FUNCNAME[1]%%.*}\"; }\n"
+    }
+    fn on_stale(&self, stale_check: StaleCheck, fzs_name: &str, rebuild_cmd: &str) -> String {
+        match stale_check {
+            StaleCheck::Off => String::new(),
+            StaleCheck::Warn => format!(
+                "  echo \"{}: plugins changed since the last build, run fzs build\" >&2",
+                self.double_quoted(fzs_name)
+            ),
+            StaleCheck::Rebuild => format!(
+                "  if {}; then\n    _fzs_rebuilt=1 source \"$fzs_init_file\"\n    return\n  fi",
+                rebuild_cmd
+            ),
+        }
+    }
+}
+
+pub struct Fish;
+
+// zsh's caret notation (^[p, ^X^P) to fish's (\ep, \cx\cp), other keys are passed through
+pub fn fish_key(key: &str) -> String {
+    readline_key(key).replace("\\C-", "\\c")
+}
+
+impl Backend for Fish {
+    fn extension(&self) -> &'static str {
+        "fish"
+    }
+    fn rc_file(&self) -> &'static str {
+        "~/.config/fish/config.fish"
+    }
+    fn init_template(&self) -> &'static str {
+        include_str!("../files/fzs_init.fish")
+    }
+    fn selector_template(&self) -> &'static str {
+        include_str!("../files/template.fish")
+    }
+    fn stale_check_template(&self) -> &'static str {
+        include_str!("../files/stale_check.fish")
+    }
+
+    fn alias(&self, alias: &str, cmd: &str) -> String {
+        format!("{}{} \"{}\"", self.alias_prefix(), self.word(alias), self.double_quoted(cmd))
+    }
+    fn function(&self, name: &str, cmd: &str) -> String {
+        format!("function {}; {} $argv; end", name, cmd)
+    }
+    fn background(&self, name: &str, cmd: &str) -> String {
+        format!(
+            "function {}; pueue add --escape -- {} $argv >/dev/null 2>&1; end",
+            name, cmd
+        )
+    }
+    // fish has no way to tell whether a function runs from a bind, so binds pass --widget, see bind
+    fn widget(&self, flag: &FnFlag, name: &str, cmd: &str) -> Vec<String> {
+        let widget = match flag {
+            FnFlag::WJR => format!(
+                "set -l INIT_BUFFER (commandline | string collect); set -l INIT_CURSOR (commandline -C); {}; commandline -r -- $INIT_BUFFER; commandline -C -- $INIT_CURSOR; commandline -f repaint",
+                cmd
+            ),
+            FnFlag::WJSUB => format!(
+                "commandline -i -- \"$({} | string join ' ') \"",
+                cmd
+            ),
+            FnFlag::WR => format!(
+                "commandline -r -- \"{} \"; commandline -f repaint",
+                self.double_quoted(cmd)
+            ),
+            FnFlag::WSUB => format!(
+                "read -P \"Args: \" wgArgs; commandline -i -- \"$({} (string split -n ' ' -- $wgArgs) | string join ' ') \"",
+                cmd
+            ),
+            _ => return Vec::new(),
+        };
+        vec![format!(
+            "function {}; if test \"$argv[1]\" = --widget; {}; else; {} $argv; end; end",
+            name, widget, cmd
+        )]
+    }
+    // fish's emacs bindings live in its default mode, which vi mode uses for command mode
+    fn bind(&self, key: &str, widget: &str, keymap: Option<Keymap>) -> String {
+        let mode = match keymap {
+            Some(Keymap::Viins) => "-M insert ",
+            Some(Keymap::Vicmd) => "-M default ",
+            Some(Keymap::Emacs) | None => "",
+        };
+        format!(
+            "{}{}'{}' '{}'",
+            self.bind_prefix(),
+            mode,
+            self.single_quoted(&fish_key(key)),
+            self.single_quoted(&format!("{} --widget", self.word(widget)))
+        )
+    }
+    fn bind_prefix(&self) -> &'static str {
+        "bind "
+    }
+    fn default_binds(&self) -> &'static [(&'static str, &'static str)] {
+        keybind::FISH_DEFAULTS
+    }
+    // fish has no per-command assignments for builtins like source, so the env is scoped to a block
+    fn source(&self, env: &[(String, String)], sources: &[String]) -> String {
+        let env: String = env
+            .iter()
+            .map(|(key, val)| format!("  set -l {} {}\n", key, self.word(val)))
+            .collect();
+        if sources.len() > 1 {
+            format!(
+                "\nbegin\n{}  cat {} | source\nend\n",
+                env,
+                sources.join(" ")
+            )
+        } else {
+            format!("\nbegin\n{}  source {}\nend\n", env, sources.join(" "))
+        }
+    }
+    fn this_fn(&self) -> &'static str {
+        "function this; set -l fns (status stack-trace | string match -rg \"^in function '([^.']*)\"); echo $fns[2]; end\n"
+    }
+    fn double_quoted(&self, s: &str) -> String {
+        quote::fish_double(s)
+    }
+    fn single_quoted(&self, s: &str) -> String {
+        quote::fish_single(s)
+    }
+    fn word(&self, s: &str) -> String {
+        quote::fish_word(s)
+    }
+    fn on_stale(&self, stale_check: StaleCheck, fzs_name: &str, rebuild_cmd: &str) -> String {
+        match stale_check {
+            StaleCheck::Off => String::new(),
+            StaleCheck::Warn => format!(
+                "  echo \"{}: plugins changed since the last build, run fzs build\" >&2",
+                self.double_quoted(fzs_name)
+            ),
+            StaleCheck::Rebuild => format!(
+                "  if {}\n    set -g _fzs_rebuilt 1\n    source \"$fzs_init_file\"\n    set -e _fzs_rebuilt\n    return\n  end",
+                rebuild_cmd
+            ),
+        }
+    }
+}
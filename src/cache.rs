@@ -0,0 +1,144 @@
+use crate::diagnostics::{Diagnostic, Location};
+use crate::provenance::Provenance;
+use crate::{Fun, GlobalConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Identity of a scanned file: a rename, an edit or a chmod all change it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stamp {
+    path: PathBuf,
+    mtime: i64,
+    mtime_nsec: i64,
+    len: u64,
+    inode: u64,
+    mode: u32,
+}
+
+impl Stamp {
+    pub fn of(path: &Path) -> io::Result<Stamp> {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let (inode, mode) = inode_and_mode(&metadata);
+        Ok(Stamp {
+            path: path.to_path_buf(),
+            mtime: mtime.as_secs() as i64,
+            mtime_nsec: i64::from(mtime.subsec_nanos()),
+            len: metadata.len(),
+            inode,
+            mode,
+        })
+    }
+}
+
+#[cfg(unix)]
+fn inode_and_mode(metadata: &fs::Metadata) -> (u64, u32) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.ino(), metadata.mode())
+}
+
+// Elsewhere there is no inode, and of the mode only the read-only attribute
+#[cfg(not(unix))]
+fn inode_and_mode(metadata: &fs::Metadata) -> (u64, u32) {
+    (0, u32::from(metadata.permissions().readonly()))
+}
+
+// What populate_plugins got out of one plugin or linkedbin directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachedDir {
+    pub stamps: Vec<Stamp>,
+    pub fns: Vec<CachedFun>,
+    pub sources: Vec<PathBuf>,
+    #[serde(default)]
+    pub bash_sources: Vec<PathBuf>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// Fun::location and Fun::provenance are not part of the config format, so they are stored next to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedFun {
+    pub fun: Fun,
+    pub location: Location,
+    pub provenance: Provenance,
+}
+
+// Bumped whenever scanning fills in more or differently, i.e. Fun or CachedDir gains a field, so that older caches are not read
+const FORMAT: u32 = 9;
+
+// Scan results kept in data_dir so that `fzs build --quick` only re-parses the directories that changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScanCache {
+    settings: String,
+    dirs: BTreeMap<PathBuf, CachedDir>,
+    #[serde(skip)]
+    previous: BTreeMap<PathBuf, CachedDir>,
+}
+
+impl ScanCache {
+    pub fn path(global_config: &GlobalConfig) -> PathBuf {
+        global_config.data_dir.join("scan_cache.json")
+    }
+
+    // The parse of a directory also depends on these settings and on fzs itself
+    fn settings_key(global_config: &GlobalConfig) -> String {
+        format!(
+            "{}.{} {} {} {} {} {} {:?} {} {}",
+            env!("CARGO_PKG_VERSION"),
+            FORMAT,
+            global_config.plugin_regex,
+            global_config.linkedbin_regex,
+            global_config.fn_regex,
+            global_config.name_from_cmd_regex,
+            global_config.name_from_alias_template,
+            global_config.shell,
+            global_config.source_extensions.join(","),
+            global_config.bash_source_extensions.join(","),
+        )
+    }
+
+    pub fn new(global_config: &GlobalConfig) -> ScanCache {
+        ScanCache {
+            settings: ScanCache::settings_key(global_config),
+            ..Default::default()
+        }
+    }
+
+    // A missing, unreadable or outdated cache is just empty
+    pub fn load(global_config: &GlobalConfig) -> ScanCache {
+        let mut cache = ScanCache::new(global_config);
+        let path = ScanCache::path(global_config);
+        match fs::read_to_string(&path).map(|s| serde_json::from_str::<ScanCache>(&s)) {
+            Ok(Ok(loaded)) if loaded.settings == cache.settings => cache.previous = loaded.dirs,
+            Ok(Ok(_)) => log::info!("Settings changed since the last scan, rescanning everything"),
+            Ok(Err(e)) => log::warn!("Ignoring invalid scan cache {}: {}", path.display(), e),
+            Err(_) => log::info!("No scan cache found at {}", path.display()),
+        }
+        cache
+    }
+
+    pub fn save(&self, global_config: &GlobalConfig) -> io::Result<()> {
+        let path = ScanCache::path(global_config);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(
+            &path,
+            serde_json::to_string(self).map_err(io::Error::other)?,
+        )
+    }
+
+    // The previous scan of dir, if none of its files changed since
+    pub fn take(&mut self, dir: &Path, stamps: &[Stamp]) -> Option<CachedDir> {
+        self.previous
+            .remove(dir)
+            .filter(|cached| cached.stamps == stamps)
+    }
+
+    pub fn insert(&mut self, dir: &Path, cached: CachedDir) {
+        self.dirs.insert(dir.to_path_buf(), cached);
+    }
+}
@@ -0,0 +1,86 @@
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(name = "fzs", version, about)]
+pub struct Cli {
+    /// Path to config.toml (default: $XDG_CONFIG_HOME/fzs/config.toml)
+    #[arg(short, long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Overrides settings.root_dir: the directory scanned for plugins
+    #[arg(long, global = true, value_name = "DIR")]
+    pub root_dir: Option<String>,
+
+    /// Overrides settings.data_dir: where the generated scripts are written
+    #[arg(long, global = true, value_name = "DIR")]
+    pub data_dir: Option<String>,
+
+    /// Increase logging (-v info, -vv debug, -vvv trace). RUST_LOG takes precedence.
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Scan plugins, generate selectors and symlink actions (default)
+    Build {
+        /// Print the symlinks, keybinds, aliases and files that would change, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Only re-parse plugin directories that changed since the last build and only write changed files. Fast enough for shell startup.
+        #[arg(long)]
+        quick: bool,
+    },
+    /// List scanned plugins and their actions
+    List {
+        /// text for reading, json or tsv (one action per row, with a header) for other tools
+        #[arg(long, value_enum, default_value_t = ListFormat::Text)]
+        format: ListFormat,
+    },
+    /// Scan plugins and report problems without writing anything
+    Check,
+    /// Remove generated files and the symlinks in path_dir
+    Clean,
+    /// Rebuild (like build --quick) whenever root_dir, config.toml or the template_file change
+    Watch,
+    /// Reorder the selectors by the selections recorded in history.tsv (like build --quick)
+    Rank {
+        /// Print each selector's order with the usage of its actions instead of rebuilding
+        #[arg(long)]
+        show: bool,
+    },
+    /// Show how an action is resolved
+    Explain {
+        /// The provisioned name (i.e. v.dlv), an alias, or plugin.name
+        #[arg(required_unless_present = "generated_line")]
+        action: Option<String>,
+        /// Instead, show what produced this line of a generated file, i.e. from a zsh error at fzs_plugins.zsh:812
+        #[arg(long, value_name = "N", conflicts_with = "action")]
+        generated_line: Option<usize>,
+        /// The generated file of --generated-line (default: generated_file)
+        #[arg(long, value_name = "FILE", requires = "generated_line")]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    Text,
+    Json,
+    Tsv,
+}
+
+impl Cli {
+    pub fn log_level(&self) -> &'static str {
+        match self.verbose {
+            0 => "warn",
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        }
+    }
+}
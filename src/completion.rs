@@ -0,0 +1,71 @@
+use crate::quote;
+
+// The actions and aliases of one plugin, listed together in the completion menu under header
+pub struct Group {
+    pub plugin: String,
+    pub header: String,
+    pub entries: Vec<(String, String)>, // name and desc
+    pub completers: Vec<(String, String)>, // action and the complete= it asked for
+}
+
+// A _describe spec, name:desc with the colons of name escaped
+fn spec(name: &str, desc: &str) -> String {
+    let name = name.replace(':', "\\:");
+    if desc.is_empty() {
+        name
+    } else {
+        format!("{}:{}", name, desc)
+    }
+}
+
+// An argument completer for an action: a completion function like _files, or a command whose completion to reuse
+fn compdef(action: &str, completer: &str) -> String {
+    if completer.starts_with('_') {
+        format!("compdef {} {}", quote::posix_word(completer), quote::posix_word(action))
+    } else {
+        format!("compdef {}", quote::posix_word(&format!("{}={}", action, completer)))
+    }
+}
+
+// Adds the groups to whatever completes command names, so v.<TAB> lists the actions of v with their desc. compdef only
+// exists once compinit ran, so the init file has to be sourced after it.
+pub fn zsh(groups: &[Group], fzs_name: &str) -> String {
+    let actions_fn = format!("{}._complete-actions", fzs_name);
+    let command_fn = format!("{}._complete-command", fzs_name);
+    let mut out = String::from("(( $+functions[compdef] )) || return 0\n\n");
+
+    out.push_str(&format!("{} () {{\n  local -a actions\n  local ret=1\n", actions_fn));
+    for group in groups {
+        let specs: Vec<String> = group
+            .entries
+            .iter()
+            .map(|(name, desc)| quote::posix_word(&spec(name, desc)))
+            .collect();
+        out.push_str(&format!(
+            "  actions=({})\n  _describe -t {} {} actions && ret=0\n",
+            specs.join(" "),
+            quote::posix_word(&format!("fzs-{}", group.plugin)),
+            quote::posix_word(&group.header)
+        ));
+    }
+    out.push_str("  return ret\n}\n\n");
+
+    // chains to the completer it replaces, _autocd by default, which completes the commands in path_dir once more
+    out.push_str(&format!(
+        "[[ ${{_comps[-command-]}} == {cmd} ]] || typeset -g _fzs_command_fallback=\"${{_comps[-command-]:-_autocd}}\"\n\
+         {cmd} () {{\n  local ret=1\n  {actions} && ret=0\n  \"$_fzs_command_fallback\" \"$@\" && ret=0\n  return ret\n}}\n\
+         compdef {cmd} -command-\n",
+        cmd = command_fn,
+        actions = actions_fn
+    ));
+    out.push_str("zstyle ':completion:*:*:-command-:*:fzs-*' group-name ''\n");
+    out.push_str("zstyle ':completion:*:*:-command-:*:fzs-*' format '%B%d%b'\n");
+
+    for group in groups {
+        for (action, completer) in &group.completers {
+            out.push_str(&compdef(action, completer));
+            out.push('\n');
+        }
+    }
+    out
+}
@@ -0,0 +1,175 @@
+use crate::{pathbuf_to_string, GlobalConfig, ScanningError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// Where a scanned item comes from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub plugin: String,
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>, // 1-based
+    #[serde(default)]
+    pub column: Option<usize>, // 1-based, of the line or, without one, of the file name
+}
+
+impl Location {
+    pub fn plugin(plugin: &str) -> Location {
+        Location {
+            plugin: plugin.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn file(plugin: &str, file: &Path) -> Location {
+        Location {
+            plugin: plugin.to_string(),
+            file: Some(file.to_path_buf()),
+            line: None,
+            column: None,
+        }
+    }
+
+    pub fn at_line(&self, line: usize) -> Location {
+        Location {
+            line: Some(line),
+            ..self.clone()
+        }
+    }
+
+    pub fn at_column(&self, column: usize) -> Location {
+        Location {
+            column: Some(column),
+            ..self.clone()
+        }
+    }
+
+    pub fn display<'a>(&'a self, global_config: &'a GlobalConfig) -> impl fmt::Display + 'a {
+        DisplayLocation(self, global_config)
+    }
+}
+
+struct DisplayLocation<'a>(&'a Location, &'a GlobalConfig);
+
+impl fmt::Display for DisplayLocation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.0.file, self.0.line, self.0.column) {
+            (Some(file), Some(line), Some(column)) => write!(f, "{}:{}:{}", pathbuf_to_string(file, self.1), line, column),
+            (Some(file), Some(line), None) => write!(f, "{}:{}", pathbuf_to_string(file, self.1), line),
+            (Some(file), None, Some(column)) => write!(f, "{} (column {} of the name)", pathbuf_to_string(file, self.1), column),
+            (Some(file), None, None) => write!(f, "{}", pathbuf_to_string(file, self.1)),
+            _ => write!(f, "{}", pathbuf_to_string(&self.1.config_file, self.1)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: Location,
+    pub message: String,
+}
+
+// Collects problems found while scanning so that a broken tree can be fixed in one go
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn warn(&mut self, location: &Location, message: impl Into<String>) {
+        self.push(Severity::Warning, location, message.into());
+    }
+
+    pub fn error(&mut self, location: &Location, message: impl Into<String>) {
+        self.push(Severity::Error, location, message.into());
+    }
+
+    fn push(&mut self, severity: Severity, location: &Location, message: String) {
+        log::debug!("{:?} at {:?}: {}", severity, location, message);
+        self.items.push(Diagnostic {
+            severity,
+            location: location.clone(),
+            message,
+        });
+    }
+
+    // Records a failed step as an error and keeps scanning. IO errors are still fatal.
+    pub fn check<T>(
+        &mut self,
+        location: &Location,
+        result: Result<T, ScanningError>,
+    ) -> Result<Option<T>, ScanningError> {
+        match result {
+            Ok(val) => Ok(Some(val)),
+            Err(ScanningError::Io(e)) => Err(ScanningError::Io(e)),
+            Err(e) => {
+                self.error(location, e.to_string());
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    // Diagnostics recorded since len() returned `from`, see ScanCache
+    pub fn since(&self, from: usize) -> Vec<Diagnostic> {
+        self.items[from..].to_vec()
+    }
+
+    pub fn extend(&mut self, diagnostics: Vec<Diagnostic>) {
+        self.items.extend(diagnostics);
+    }
+
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.items.iter().filter(|d| d.severity == severity).count()
+    }
+
+    // Prints errors, then warnings unless quiet, each grouped by plugin
+    pub fn report(&self, global_config: &GlobalConfig, quiet: bool) {
+        for (severity, title) in [(Severity::Error, "error"), (Severity::Warning, "warning")] {
+            let count = self.count(severity);
+            if count == 0 || (quiet && severity == Severity::Warning) {
+                continue;
+            }
+            let mut by_plugin: BTreeMap<&str, Vec<&Diagnostic>> = BTreeMap::new();
+            for diagnostic in self.items.iter().filter(|d| d.severity == severity) {
+                by_plugin
+                    .entry(diagnostic.location.plugin.as_str())
+                    .or_default()
+                    .push(diagnostic);
+            }
+            eprintln!("{} {}{}:", count, title, if count == 1 { "" } else { "s" });
+            for (plugin, diagnostics) in by_plugin {
+                eprintln!(
+                    "  {}",
+                    if plugin.is_empty() {
+                        "(global)"
+                    } else {
+                        plugin
+                    }
+                );
+                for diagnostic in diagnostics {
+                    eprintln!(
+                        "    {}: {}",
+                        diagnostic.location.display(global_config),
+                        diagnostic.message
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{quote, sorted_plugins, Fun, GlobalConfig, Initial, Plugin};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The scanned model as `fzs list --format json` prints it, keyed by plugin name
+#[derive(Serialize)]
+struct ExportedPlugin<'a> {
+    alias: &'a str,
+    desc: Option<&'a str>,
+    path: &'a Path,
+    sources: &'a [PathBuf],
+    bash_sources: &'a [PathBuf],
+    binds: &'a [String],
+    menu_binds: &'a [String],
+    actions: Vec<ExportedFun<'a>>,
+}
+
+#[derive(Serialize)]
+struct ExportedFun<'a> {
+    name: &'a str,
+    alias: Option<&'a str>,
+    desc: Option<&'a str>,
+    flags: Vec<String>,
+    binds: &'a [String],
+    cmd: String, // what it is called by, see Fun::get_cmd
+    real_cmd: String, // what that runs, see Fun::get_real_cmd
+    bin: Option<&'a Path>,
+    file: Option<&'a Path>, // where it was declared, None for actions declared in config.toml
+    line: Option<usize>,
+    key: Option<&'a str>, // the menu key it got
+    included_from: Option<&'a str>,
+}
+
+impl<'a> ExportedFun<'a> {
+    fn new(fun: &'a Fun, pg: &Plugin, global_config: &GlobalConfig) -> ExportedFun<'a> {
+        let mut flags: Vec<String> = fun.flags.iter().map(|flag| flag.to_string()).collect();
+        flags.sort();
+        ExportedFun {
+            name: &fun.name,
+            alias: fun.alias.as_deref(),
+            desc: fun.desc.as_deref(),
+            flags,
+            binds: &fun.binds,
+            cmd: fun.get_cmd(pg, global_config),
+            real_cmd: fun.get_real_cmd(pg, global_config),
+            bin: fun.bin.as_deref(),
+            file: fun.location.file.as_deref(),
+            line: fun.location.line,
+            key: fun.menu_key.as_deref(),
+            included_from: fun.included_from.as_deref(),
+        }
+    }
+}
+
+pub fn json(plugins: &HashMap<String, Plugin>, global_config: &GlobalConfig) -> Result<String, io::Error> {
+    let exported: BTreeMap<&str, ExportedPlugin> = sorted_plugins(plugins)
+        .into_iter()
+        .map(|pg| {
+            let plugin = ExportedPlugin {
+                alias: pg.get_alias_ref(),
+                desc: pg.desc.as_deref(),
+                path: &pg.path,
+                sources: &pg.sources,
+                bash_sources: &pg.bash_sources,
+                binds: &pg.binds,
+                menu_binds: &pg.menu_binds,
+                actions: pg
+                    .sorted_fns()
+                    .into_iter()
+                    .map(|fun| ExportedFun::new(fun, pg, global_config))
+                    .collect(),
+            };
+            (pg.name.as_str(), plugin)
+        })
+        .collect();
+    serde_json::to_string_pretty(&exported).map_err(io::Error::other)
+}
+
+const TSV_HEADER: [&str; 12] = [
+    "plugin", "name", "alias", "desc", "flags", "binds", "cmd", "real_cmd", "bin", "file", "line", "key",
+];
+
+// One row per action, lists are comma separated and missing values empty
+pub fn tsv(plugins: &HashMap<String, Plugin>, global_config: &GlobalConfig) -> String {
+    let mut out = TSV_HEADER.join("\t");
+    out.push('\n');
+    for pg in sorted_plugins(plugins) {
+        for fun in pg.sorted_fns() {
+            let fun = ExportedFun::new(fun, pg, global_config);
+            let path = |path: Option<&Path>| path.map(|path| path.to_string_lossy().into_owned()).unwrap_or_default();
+            let row = [
+                pg.name.clone(),
+                fun.name.to_string(),
+                fun.alias.unwrap_or_default().to_string(),
+                fun.desc.unwrap_or_default().to_string(),
+                fun.flags.join(","),
+                fun.binds.join(","),
+                fun.cmd,
+                fun.real_cmd,
+                path(fun.bin),
+                path(fun.file),
+                fun.line.map(|line| line.to_string()).unwrap_or_default(),
+                fun.key.unwrap_or_default().to_string(),
+            ];
+            out.push_str(&row.iter().map(|cell| quote::table_field(cell)).collect::<Vec<String>>().join("\t"));
+            out.push('\n');
+        }
+    }
+    out
+}
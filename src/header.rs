@@ -0,0 +1,74 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+// Only the top of an executable is read, a header below a license or usage comment still counts
+const MAX_LINES: usize = 20;
+const COMMENT_MARKERS: [&str; 4] = ["#", "//", "--", ";"];
+const HEADER_PREFIX: &str = "fzs:";
+
+// The modifiers of the `fzs:` comments near the top of an executable, i.e. `# fzs: WJR binds=^[r alias=r`, with the
+// line and column they start at. Binary executables have none.
+pub fn read(path: &Path) -> io::Result<Vec<(usize, usize, String)>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut modifiers = Vec::new();
+    for (index, line) in reader.lines().take(MAX_LINES).enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => break,
+            Err(e) => return Err(e),
+        };
+        if let Some(header) = strip_comment(&line).and_then(|comment| comment.trim_start().strip_prefix(HEADER_PREFIX)) {
+            let column = line[..line.len() - header.len()].chars().count() + 1;
+            modifiers.push((index + 1, column, header.to_string()));
+        }
+    }
+    Ok(modifiers)
+}
+
+fn strip_comment(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    COMMENT_MARKERS
+        .iter()
+        .find_map(|marker| line.strip_prefix(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn headers(contents: &[u8]) -> Vec<(usize, usize, String)> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("action");
+        fs::write(&path, contents).unwrap();
+        read(&path).unwrap()
+    }
+
+    #[test]
+    fn comment_markers() {
+        let script = "#!/bin/sh\n# fzs: WG\n// fzs: alias=a\n  -- fzs: key=k\n;fzs: desc=semi\nfzs: not a comment\n# fzs:\n";
+        assert_eq!(
+            headers(script.as_bytes()),
+            [
+                (2, 7, " WG".to_string()),
+                (3, 8, " alias=a".to_string()),
+                (4, 10, " key=k".to_string()),
+                (5, 6, " desc=semi".to_string()),
+                (7, 7, "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_the_first_lines_count() {
+        let mut script = "# usage\n".repeat(MAX_LINES - 1);
+        script.push_str("# fzs: WG\n# fzs: CMD\n");
+        assert_eq!(headers(script.as_bytes()), [(MAX_LINES, 7, " WG".to_string())]);
+    }
+
+    #[test]
+    fn binaries_have_no_header() {
+        assert!(headers(b"\x7fELF\x02\x01\xff\xfe\n# fzs: WG\n").is_empty());
+    }
+}
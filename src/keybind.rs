@@ -0,0 +1,446 @@
+use crate::diagnostics::{Diagnostics, Location};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// The keymap binds go into, set with `keymap` in [settings] or per plugin. Without one binds go into the main keymap.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Keymap {
+    Emacs,
+    Viins,
+    Vicmd,
+}
+
+// Keys that send a plain sequence, and what ctrl- and alt- turn them into
+const PLAIN_KEYS: &[(&str, &str, &str, &str)] = &[
+    // name, key, ctrl-, alt-
+    ("space", " ", "^@", "^[ "),
+    ("tab", "^I", "^I", "^[^I"),
+    ("enter", "^M", "^M", "^[^M"),
+    ("esc", "^[", "^[", "^[^["),
+    ("backspace", "^?", "^H", "^[^?"),
+];
+
+// Keys that send a CSI sequence, xterm style: ^[[A, or ^[[1;5A with modifiers. The F1-F4 keys send ^[OP without
+// modifiers, which is the first column.
+const CSI_KEYS: &[(&str, &str, &str)] = &[
+    ("up", "^[[A", "A"),
+    ("down", "^[[B", "B"),
+    ("right", "^[[C", "C"),
+    ("left", "^[[D", "D"),
+    ("home", "^[[H", "H"),
+    ("end", "^[[F", "F"),
+    ("f1", "^[OP", "P"),
+    ("f2", "^[OQ", "Q"),
+    ("f3", "^[OR", "R"),
+    ("f4", "^[OS", "S"),
+];
+
+// Keys that send ^[[n~, or ^[[n;5~ with modifiers
+const TILDE_KEYS: &[(&str, u8)] = &[
+    ("insert", 2),
+    ("delete", 3),
+    ("pageup", 5),
+    ("pagedown", 6),
+    ("f5", 15),
+    ("f6", 17),
+    ("f7", 18),
+    ("f8", 19),
+    ("f9", 20),
+    ("f10", 21),
+    ("f11", 23),
+    ("f12", 24),
+];
+
+const MODIFIERS: [&str; 4] = ["ctrl-", "alt-", "meta-", "shift-"];
+
+fn is_named(key: &str) -> bool {
+    PLAIN_KEYS.iter().any(|(name, ..)| *name == key)
+        || CSI_KEYS.iter().any(|(name, ..)| *name == key)
+        || TILDE_KEYS.iter().any(|(name, _)| *name == key)
+}
+
+// One key with its modifiers, i.e. ctrl-x, alt-w or shift-down
+fn chord(token: &str) -> Result<String, String> {
+    let (mut ctrl, mut alt, mut shift) = (false, false, false);
+    let mut key = token;
+    while let Some(modifier) = MODIFIERS.iter().find(|modifier| key.len() > modifier.len() && key.starts_with(*modifier)) {
+        match *modifier {
+            "ctrl-" => ctrl = true,
+            "shift-" => shift = true,
+            _ => alt = true,
+        }
+        key = &key[modifier.len()..];
+    }
+    // xterm's modifier parameter
+    let param = 1 + shift as u8 + 2 * alt as u8 + 4 * ctrl as u8;
+
+    if let Some((_, plain, csi)) = CSI_KEYS.iter().find(|(name, ..)| *name == key) {
+        return Ok(if param == 1 { plain.to_string() } else { format!("^[[1;{}{}", param, csi) });
+    }
+    if let Some((_, n)) = TILDE_KEYS.iter().find(|(name, _)| *name == key) {
+        return Ok(if param == 1 { format!("^[[{}~", n) } else { format!("^[[{};{}~", n, param) });
+    }
+    if key == "tab" && shift && !ctrl && !alt {
+        return Ok("^[[Z".to_string());
+    }
+    if shift && key.chars().count() != 1 {
+        return Err(format!("shift- only applies to letters and to arrow, function and editing keys in {}", token));
+    }
+    if let Some((_, plain, with_ctrl, with_alt)) = PLAIN_KEYS.iter().find(|(name, ..)| *name == key) {
+        return match (ctrl, alt) {
+            (false, false) => Ok(plain.to_string()),
+            (true, false) => Ok(with_ctrl.to_string()),
+            (false, true) => Ok(with_alt.to_string()),
+            (true, true) => Err(format!("ctrl-alt- is not supported for {} in {}", key, token)),
+        };
+    }
+
+    let mut chars = key.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return Err(format!("Unknown key {} in {}", key, token));
+    };
+    let c = if shift { c.to_ascii_uppercase() } else { c };
+    let mut sequence = String::new();
+    if alt {
+        sequence.push_str("^[");
+    }
+    if ctrl {
+        if !c.is_ascii_alphabetic() && !"@[\\]^_?".contains(c) {
+            return Err(format!("ctrl- only applies to letters and @[\\]^_? in {}", token));
+        }
+        sequence.push('^');
+        sequence.push(c.to_ascii_uppercase());
+    } else {
+        sequence.push(c);
+    }
+    Ok(sequence)
+}
+
+// The key sequence of a bind, in zsh's caret notation. Binds are either raw sequences like ^[[1;2B or ^x^p, which are
+// kept as is, or readable chords separated by spaces like `ctrl-x ctrl-p`, `alt-w` or `shift-down`. A bind counts as
+// readable when it has no ^ or \ and each word is a named key or has a modifier, so that raw binds like jk still work.
+pub fn translate(bind: &str) -> Result<String, String> {
+    let tokens: Vec<&str> = bind.split_whitespace().collect();
+    let readable = !bind.contains(['^', '\\'])
+        && !tokens.is_empty()
+        && tokens.iter().all(|token| is_named(token) || MODIFIERS.iter().any(|modifier| token.len() > modifier.len() && token.starts_with(modifier)));
+    if !readable {
+        return Ok(bind.to_string());
+    }
+    tokens.into_iter().map(chord).collect()
+}
+
+// Widgets of the default emacs keymap that a bind would silently take the place of, per shell, see Backend::default_binds
+pub const ZSH_DEFAULTS: &[(&str, &str)] = &[
+    ("^A", "beginning-of-line"),
+    ("^B", "backward-char"),
+    ("^D", "delete-char-or-list"),
+    ("^E", "end-of-line"),
+    ("^F", "forward-char"),
+    ("^G", "send-break"),
+    ("^H", "backward-delete-char"),
+    ("^I", "expand-or-complete"),
+    ("^J", "accept-line"),
+    ("^K", "kill-line"),
+    ("^L", "clear-screen"),
+    ("^M", "accept-line"),
+    ("^N", "down-line-or-history"),
+    ("^P", "up-line-or-history"),
+    ("^R", "history-incremental-search-backward"),
+    ("^S", "history-incremental-search-forward"),
+    ("^T", "transpose-chars"),
+    ("^U", "kill-whole-line"),
+    ("^W", "backward-kill-word"),
+    ("^Y", "yank"),
+    ("^?", "backward-delete-char"),
+    ("^[b", "backward-word"),
+    ("^[c", "capitalize-word"),
+    ("^[d", "kill-word"),
+    ("^[f", "forward-word"),
+    ("^[h", "run-help"),
+    ("^[l", "down-case-word"),
+    ("^[p", "history-search-backward"),
+    ("^[n", "history-search-forward"),
+    ("^[q", "push-line"),
+    ("^[t", "transpose-words"),
+    ("^[u", "up-case-word"),
+    ("^[w", "copy-region-as-kill"),
+    ("^[.", "insert-last-word"),
+    ("^[_", "insert-last-word"),
+    ("^X^E", "edit-command-line"),
+];
+
+// readline's emacs keymap, under its own names
+pub const BASH_DEFAULTS: &[(&str, &str)] = &[
+    ("^A", "beginning-of-line"),
+    ("^B", "backward-char"),
+    ("^D", "delete-char"),
+    ("^E", "end-of-line"),
+    ("^F", "forward-char"),
+    ("^G", "abort"),
+    ("^H", "backward-delete-char"),
+    ("^I", "complete"),
+    ("^J", "accept-line"),
+    ("^K", "kill-line"),
+    ("^L", "clear-screen"),
+    ("^M", "accept-line"),
+    ("^N", "next-history"),
+    ("^P", "previous-history"),
+    ("^R", "reverse-search-history"),
+    ("^S", "forward-search-history"),
+    ("^T", "transpose-chars"),
+    ("^U", "unix-line-discard"),
+    ("^W", "unix-word-rubout"),
+    ("^Y", "yank"),
+    ("^?", "backward-delete-char"),
+    ("^[b", "backward-word"),
+    ("^[c", "capitalize-word"),
+    ("^[d", "kill-word"),
+    ("^[f", "forward-word"),
+    ("^[l", "downcase-word"),
+    ("^[n", "non-incremental-forward-search-history"),
+    ("^[p", "non-incremental-reverse-search-history"),
+    ("^[r", "revert-line"),
+    ("^[t", "transpose-words"),
+    ("^[u", "upcase-word"),
+    ("^[y", "yank-pop"),
+    ("^[.", "yank-last-arg"),
+    ("^[_", "yank-last-arg"),
+    ("^X^E", "edit-and-execute-command"),
+];
+
+// fish's default key bindings
+pub const FISH_DEFAULTS: &[(&str, &str)] = &[
+    ("^A", "beginning-of-line"),
+    ("^B", "backward-char"),
+    ("^D", "delete-or-exit"),
+    ("^E", "end-of-line"),
+    ("^F", "forward-char"),
+    ("^H", "backward-delete-char"),
+    ("^I", "complete"),
+    ("^J", "execute"),
+    ("^K", "kill-line"),
+    ("^L", "clear-screen"),
+    ("^M", "execute"),
+    ("^N", "down-or-search"),
+    ("^P", "up-or-search"),
+    ("^R", "history-pager"),
+    ("^T", "transpose-chars"),
+    ("^U", "backward-kill-line"),
+    ("^W", "backward-kill-path-component"),
+    ("^Y", "yank"),
+    ("^?", "backward-delete-char"),
+    ("^[b", "backward-word"),
+    ("^[c", "capitalize-word"),
+    ("^[d", "kill-word"),
+    ("^[e", "edit_command_buffer"),
+    ("^[f", "forward-word"),
+    ("^[h", "__fish_man_page"),
+    ("^[l", "__fish_list_current_token"),
+    ("^[p", "__fish_paginate"),
+    ("^[s", "fish_commandline_prepend sudo"),
+    ("^[t", "transpose-words"),
+    ("^[u", "upcase-word"),
+    ("^[v", "edit_command_buffer"),
+    ("^[w", "__fish_whatis_current_token"),
+    ("^[.", "history-token-search-backward"),
+];
+
+// Spells a key sequence the way zsh's bindkey does, so that ^[s, \es and \M-s, or ^x and \C-x, compare equal
+pub fn normalize(bind: &str) -> String {
+    let mut normalized = String::new();
+    let mut chars = bind.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '^' => match chars.next() {
+                Some(next) => {
+                    normalized.push('^');
+                    normalized.push(next.to_ascii_uppercase());
+                }
+                None => normalized.push('^'),
+            },
+            '\\' => match chars.next() {
+                Some('e') | Some('E') => normalized.push_str("^["),
+                Some('M') if chars.peek() == Some(&'-') => {
+                    chars.next();
+                    normalized.push_str("^[");
+                }
+                Some('C') if chars.peek() == Some(&'-') => {
+                    chars.next();
+                    normalized.push('^');
+                    normalized.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+                }
+                Some('c') => {
+                    normalized.push('^');
+                    normalized.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+                }
+                Some(next) => {
+                    normalized.push('\\');
+                    normalized.push(next);
+                }
+                None => normalized.push('\\'),
+            },
+            _ => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+// Who binds a sequence: an action, a plugin's selector or one of the global selectors
+#[derive(Debug)]
+pub struct Owner {
+    pub name: String,
+    pub location: Location,
+    pub overrides: bool, // set with override = true, shadowing a default is intended
+    pub keymap: Option<Keymap>,
+}
+
+// A keymap and a normalized sequence
+type Sequence = (Option<Keymap>, String);
+
+// Every bind that ends up in the generated files, with the bind as written
+#[derive(Debug, Default)]
+pub struct Registry {
+    binds: BTreeMap<Sequence, Vec<(String, Owner)>>,
+}
+
+impl Registry {
+    // Reports binds in unknown readable notation instead of registering them
+    pub fn add(&mut self, bind: &str, owner: Owner, diagnostics: &mut Diagnostics) {
+        if bind.is_empty() {
+            return;
+        }
+        match translate(bind) {
+            Ok(sequence) => self
+                .binds
+                .entry((owner.keymap, normalize(&sequence)))
+                .or_default()
+                .push((bind.to_string(), owner)),
+            Err(e) => diagnostics.error(&owner.location, format!("Invalid keybind {}: {}", bind, e)),
+        }
+    }
+
+    // Warns about sequences bound more than once, where whichever is sourced last wins, and about binds that replace one
+    // of the shell's defaults without override
+    pub fn check(&self, defaults: &[(&str, &str)], diagnostics: &mut Diagnostics) {
+        for ((keymap, sequence), owners) in &self.binds {
+            if owners.len() > 1 {
+                let names = owners
+                    .iter()
+                    .map(|(bind, owner)| format!("{} ({})", owner.name, bind))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                for (_, owner) in owners {
+                    diagnostics.warn(
+                        &owner.location,
+                        format!("Keybind {} is bound more than once: {}", sequence, names),
+                    );
+                }
+            }
+            let emacs = matches!(keymap, None | Some(Keymap::Emacs));
+            if let Some((_, widget)) = defaults.iter().find(|(default, _)| emacs && default == sequence) {
+                for (bind, owner) in owners.iter().filter(|(_, owner)| !owner.overrides) {
+                    diagnostics.warn(
+                        &owner.location,
+                        format!(
+                            "Keybind {} of {} shadows the default {}, set override = true if that is intended",
+                            bind, owner.name, widget
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Shell;
+
+    fn owner(name: &str, overrides: bool) -> Owner {
+        Owner {
+            name: name.to_string(),
+            location: Location::plugin("files"),
+            overrides,
+            keymap: None,
+        }
+    }
+
+    // The warnings check gives for binds, each with its owner's name and override
+    fn check(binds: &[(&str, &str, bool)], defaults: &[(&str, &str)]) -> Vec<String> {
+        let mut diagnostics = Diagnostics::default();
+        let mut registry = Registry::default();
+        for (bind, name, overrides) in binds {
+            registry.add(bind, owner(name, *overrides), &mut diagnostics);
+        }
+        registry.check(defaults, &mut diagnostics);
+        diagnostics.since(0).into_iter().map(|d| d.message).collect()
+    }
+
+    // The warnings check gives a bind of ^[l
+    fn warnings(shell: Shell) -> Vec<String> {
+        check(&[("^[l", "lf", false)], shell.backend().default_binds())
+    }
+
+    #[test]
+    fn shadowed_defaults_of_each_shell() {
+        for (shell, widget) in [
+            (Shell::Zsh, "down-case-word"),
+            (Shell::Bash, "downcase-word"),
+            (Shell::Fish, "__fish_list_current_token"),
+        ] {
+            assert_eq!(
+                warnings(shell),
+                [format!("Keybind ^[l of lf shadows the default {}, set override = true if that is intended", widget)]
+            );
+        }
+    }
+
+    #[test]
+    fn spellings_of_a_sequence_are_one_bind() {
+        let duplicate = r"Keybind ^[s is bound more than once: a (^[s), b (\es), c (\M-s)";
+        assert_eq!(
+            check(&[("^[s", "a", true), (r"\es", "b", true), (r"\M-s", "c", true)], &[]),
+            [duplicate, duplicate, duplicate]
+        );
+    }
+
+    #[test]
+    fn override_suppresses_shadowing() {
+        assert_eq!(
+            check(&[("^[w", "copy", false), (r"\C-x\C-e", "edit", true)], ZSH_DEFAULTS),
+            ["Keybind ^[w of copy shadows the default copy-region-as-kill, set override = true if that is intended"]
+        );
+        assert!(check(&[("^[w", "copy", true), (r"\C-x\C-e", "edit", true)], ZSH_DEFAULTS).is_empty());
+    }
+
+    #[test]
+    fn readable_binds() {
+        for (bind, sequence) in [
+            ("ctrl-x ctrl-p", "^X^P"),
+            ("alt-w", "^[w"),
+            ("shift-down", "^[[1;2B"),
+            ("ctrl-alt-x", "^[^X"),
+            ("f5", "^[[15~"),
+            ("ctrl-f5", "^[[15;5~"),
+            ("shift-tab", "^[[Z"),
+            // raw sequences stay as written
+            ("^x^p", "^x^p"),
+            (r"\es", r"\es"),
+            ("jk", "jk"),
+        ] {
+            assert_eq!(translate(bind).as_deref(), Ok(sequence), "{}", bind);
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        assert_eq!(translate("ctrl-foo"), Err("Unknown key foo in ctrl-foo".to_string()));
+        assert!(translate("shift-space").is_err());
+        let mut diagnostics = Diagnostics::default();
+        Registry::default().add("alt-f13", owner("f", false), &mut diagnostics);
+        assert_eq!(diagnostics.errors(), 1);
+    }
+}
@@ -1,19 +1,47 @@
+mod accept;
+mod backend;
+mod cache;
+mod cli;
+mod completion;
+mod menu;
+mod modifier;
+mod diagnostics;
+mod export;
+mod header;
+mod keybind;
+mod plan;
+mod provenance;
+mod quote;
+mod rank;
+mod table;
+mod template;
+mod watch;
+
+use clap::Parser;
+use accept::{Accept, RawAccept};
+use backend::{Backend, Shell};
+use cache::{CachedDir, CachedFun, ScanCache, Stamp};
+use cli::{Cli, Commands, ListFormat};
+use diagnostics::{Diagnostics, Location};
+use keybind::Keymap;
+use plan::Plan;
+use provenance::{Layer, Op, Provenance};
+use rank::{History, Sort};
+use template::Templates;
 use derive_more::{Display, From, Into};
 use is_executable::IsExecutable;
-use log::{debug, error, info, log_enabled, trace, warn, Level};
+use log::{debug, error, info, warn};
 use env_logger::Env;
 use regex::Regex;
-use serde::Deserialize;
-use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::ffi::OsString;
-use std::fmt::{self, format};
+use std::fmt;
 use std::fs;
-use std::fs::DirEntry;
 use std::fs::File;
 use std::io;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str::FromStr;
@@ -29,10 +57,43 @@ struct Plugin {
     fns: HashMap<String, Fun>,
     sources: Vec<PathBuf>,
     #[serde(default)]
+    bash_sources: Vec<PathBuf>, // sources with a bash_source_extensions extension, unparsed and unsourced unless shell is bash
+    #[serde(default)]
     fn_template: Option<String>,
     #[serde(default)]
     fn_table_template: Option<String>,
     binds: Keybinds, //todo: convert to Vec
+    #[serde(default)]
+    menu_binds: Keybinds, // bound to the menu selector, see menu_widget_template
+    #[serde(default, rename = "override")]
+    overrides: bool, // the binds of the plugin and its actions may shadow default widgets
+    #[serde(default)]
+    keymap: Option<Keymap>, // overrides the global setting for the binds of the plugin and its actions
+    #[serde(default)]
+    include_namespace: Option<IncludeNamespace>,
+    #[serde(default)]
+    sort: Option<Sort>,
+    #[serde(default)]
+    accept: RawAccept,
+}
+
+// How actions flattened into another plugin with PGI are named
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum IncludeNamespace {
+    #[default]
+    Keep, // v.dlv stays v.dlv, the including plugin only lists it
+    Rename, // v.dlv becomes m.dlv when included into m
+}
+
+// What fzs_init.zsh does on shell startup when plugins changed since the last build
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum StaleCheck {
+    #[default]
+    Off,
+    Warn, // print a reminder to run fzs build
+    Rebuild, // run fzs build --quick and source the new init file
 }
 
 #[derive(Debug, Clone)]
@@ -45,132 +106,188 @@ struct InitialPlugin {
 
 // todo: custom one-pass replacement?
 impl Plugin {
-    fn fn_table(&self, global_config: &GlobalConfig) -> Option<String> {
-        let mut sorted:  Vec<&Fun> = self
-            .fns
-            .values()
+    // generated output should be stable across runs
+    fn sorted_fns(&self) -> Vec<&Fun> {
+        let mut sorted: Vec<&Fun> = self.fns.values().collect();
+        sorted.sort_by(|a, b| a.name.cmp(&b.name));
+        sorted
+    }
+
+    // the actions listed by the selector, in the order they are listed, see rank
+    fn ranked_fns(&self, global_config: &GlobalConfig) -> Vec<&Fun> {
+        let listed = self
+            .sorted_fns()
+            .into_iter()
+            .filter(|&fun| !fun.flags.contains(&FnFlag::NA))
+            .collect();
+        global_config.history.order(
+            listed,
+            self.sort.unwrap_or(global_config.sort),
+            |fun| fun.get_cmd(self, global_config),
+            |fun| fun.flags.contains(&FnFlag::TOP),
+        )
+    }
+
+    // Gives each listed action a key for the menu selector, see menu::assign. Goes by name rather than rank, so that keys
+    // do not move around as the history grows.
+    fn assign_menu_keys(&mut self, diagnostics: &mut Diagnostics) {
+        let listed: Vec<&Fun> = self
+            .sorted_fns()
+            .into_iter()
             .filter(|&fun| !fun.flags.contains(&FnFlag::NA))
             .collect();
-            sorted.sort_by(|a, b| a.name.cmp(&b.name));
-            
-        let table_rows = sorted
+        let entries: Vec<menu::Entry> = listed
+            .iter()
+            .map(|fun| menu::Entry { alias: fun.get_alias_ref(), name: &fun.name, preferred: fun.key.as_deref() })
+            .collect();
+        let assignment = menu::assign(&entries);
+        for (i, holder) in assignment.conflicts {
+            diagnostics.warn(
+                &listed[i].location,
+                format!(
+                    "Menu key {} of {} clashes with {} (key {}), assigning another",
+                    listed[i].key.as_deref().unwrap_or(""),
+                    listed[i].name,
+                    listed[holder].name,
+                    assignment.keys[holder].as_deref().unwrap_or(""),
+                ),
+            );
+        }
+        let keys: Vec<(String, Option<String>)> = listed
+            .iter()
+            .map(|fun| fun.name.clone())
+            .zip(assignment.keys)
+            .collect();
+        for (name, key) in keys {
+            if key.is_none() {
+                diagnostics.warn(&self.fns[&name].location, format!("No menu key left for {}", name));
+            }
+            if let Some(fun) = self.fns.get_mut(&name) {
+                fun.menu_key = key;
+            }
+        }
+    }
+
+    fn fn_table(&self, global_config: &GlobalConfig) -> Option<String> {
+        let table_rows = self
+                .ranked_fns(global_config)
                 .into_iter()
-                .map(|fun| {
-                templatize(
-                    fun.into(),
+                .map(|fun| table::Row {
+                line: templatize(
+                    fun,
                     self,
-                    &self
+                    self
                         .fn_table_template
                         .as_deref()
                         .unwrap_or(&global_config.fn_table_template),
                     global_config,
-                    false,
-                    false
-                )
+                    true
+                ),
+                category: table::Category::of(&fun.flags),
             })
-            .collect::<Vec<String>>();
+            .collect::<Vec<table::Row>>();
         if table_rows.is_empty() {
             None
         } else {
-            Some(table_rows.join("\n"))
+            Some(render_table(table_rows, global_config))
         }
     }
 
-    fn extra_table(&self, global_config: &GlobalConfig) -> Option<String> {
-        let mut lines: Vec<String> = Vec::new();
-        for fun in self.fns.values() {
-            let provisioned_cmd=fun.get_cmd(self, global_config);
-            let cmd= fun.get_real_cmd(self, global_config);
-            let prefix = if provisioned_cmd == cmd {"command "} else {""};
-
-            if !fun.flags.contains(&FnFlag::AL) && !fun.flags.contains(&FnFlag::PG) {
-                if let Some(ref alias) = fun.alias {
-                    if !alias.is_empty()  {
-                        lines.push(format!(
-                            "alias {}=\"{}\"",
-                            alias,
-                            &cmd
-                        ));
-                    } else {
-                        warn!("alias for {:?} is empty, skipping.", fun)
-                    }
+    // the actions listed by the selector, for template_file to iterate over
+    fn fns_value(&self, global_config: &GlobalConfig) -> minijinja::Value {
+        self.ranked_fns(global_config)
+            .into_iter()
+            .map(|fun| {
+                minijinja::context! {
+                    name => &fun.name,
+                    alias => fun.get_alias_ref(),
+                    desc => fun.desc.as_deref().unwrap_or(""),
+                    flags => flags_value(fun),
+                    cmd => fun.get_cmd(self, global_config),
+                    key => fun.menu_key.as_deref().unwrap_or(""),
                 }
-            }
-            if fun.flags.contains(&FnFlag::WJR) {
-                if fun.flags.contains(&FnFlag::WJR) {
-                    lines.push(format!(
-                        "{}() {{ if zle; then local INIT_BUFFER=$BUFFER; local INIT_CURSOR=$CURSOR; {}{}; BUFFER=$INIT_BUFFER; CURSOR=$INIT_CURSOR; zle redisplay; else {}{} $@; fi; }}",
-                        &provisioned_cmd,
-                        &prefix,
-                        &cmd,
-                        &prefix,
-                        &cmd
-                    ));
-                } else { // not sure if we want this
-                    lines.push(format!(
-                        "{}() {{ if zle; then zle push-input; BUFFER=\"{}{}\"; zle accept-line; else {}{} $@; fi; }}",
-                        &provisioned_cmd,
-                        &prefix,
-                        &cmd,
-                        &prefix,
-                        &cmd
-                    ));
+            })
+            .collect()
+    }
+
+    // The aliases, functions, widgets and binds the generated file defines for an action
+    fn fun_lines(&self, fun: &Fun, global_config: &GlobalConfig) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        // aliases and binds stay with the plugin the action was included from
+        let included = fun.included_from.is_some();
+        let provisioned_cmd=fun.get_cmd(self, global_config);
+        let cmd= fun.get_real_cmd(self, global_config);
+        let prefix = if provisioned_cmd == cmd {"command "} else {""};
+
+        if !included && !fun.flags.contains(&FnFlag::AL) && !fun.flags.contains(&FnFlag::PG) {
+            if let Some(ref alias) = fun.alias {
+                if !alias.is_empty()  {
+                    lines.push(global_config.backend().alias(alias, &cmd));
+                } else {
+                    warn!("alias for {:?} is empty, skipping.", fun)
                 }
-                lines.push(format!("zle -N {}", &provisioned_cmd));
-            } else if fun.flags.contains(&FnFlag::PBG) {
-                lines.push(format!(
-                    "{}() {{ pueue add  --escape -- {}{} $@ >/dev/null 2>&1; }}",
-                    &provisioned_cmd,
-                    &prefix,
-                    &cmd
-                ));
-            } else if fun.flags.contains(&FnFlag::WJSUB) {
-                lines.push(format!(
-                    "{}() {{ if zle; then LBUFFER+=\"$({}{} | tr '\n' ' \\\n') \"; else {}{} $@; fi }}",
-                    &provisioned_cmd,
-                    &prefix,
-                    &cmd,
-                    &prefix,
-                    &cmd
-                ));
-                lines.push(format!("zle -N {}", &provisioned_cmd));
-            } else if fun.flags.contains(&FnFlag::WR) { // temporarily run a command
-                lines.push(format!(
-                    "{}() {{ if zle; then zle push-input; BUFFER=\"{}{} \"; else {}{} $@; fi; }}",
-                    &provisioned_cmd,
-                    &prefix,
-                    &cmd,
-                    &prefix,
-                    &cmd
-                ));
-                lines.push(format!("zle -N {}", &provisioned_cmd));
-            } else if fun.flags.contains(&FnFlag::WSUB) {
-                lines.push(format!(
-                    "{}() {{ if zle; then local wgArgs; vared -p \"Args: \" -c wgArgs; LBUFFER+=\"$({}{} ${{(z)wgArgs}}  | tr '\n' ' \\\n') \"; else {}{} $@; fi }}",
-                    &provisioned_cmd,
-                    &prefix,
-                    &cmd,
-                    &prefix,
-                    &cmd
-                ));
-                lines.push(format!("zle -N {}", &provisioned_cmd));
             }
+        }
+        let backend = global_config.backend();
+        let prefixed_cmd = format!("{}{}", prefix, &cmd);
+        if fun.flags.contains(&FnFlag::WJR) {
+            lines.extend(backend.widget(&FnFlag::WJR, &provisioned_cmd, &prefixed_cmd));
+        } else if fun.flags.contains(&FnFlag::PBG) {
+            lines.push(backend.background(&provisioned_cmd, &prefixed_cmd));
+        } else if fun.flags.contains(&FnFlag::WJSUB) {
+            lines.extend(backend.widget(&FnFlag::WJSUB, &provisioned_cmd, &prefixed_cmd));
+        } else if fun.flags.contains(&FnFlag::WR) {
+            lines.extend(backend.widget(&FnFlag::WR, &provisioned_cmd, &prefixed_cmd));
+        } else if fun.flags.contains(&FnFlag::WSUB) {
+            lines.extend(backend.widget(&FnFlag::WSUB, &provisioned_cmd, &prefixed_cmd));
+        } else if fun.flags.contains(&FnFlag::PFN) {
+            lines.push(backend.function(&provisioned_cmd, &prefixed_cmd));
+        }
 
-            // Generate the lines for each bind
-            lines.extend(
-                fun.binds
-                    .iter()
-                    .filter(|kb| !kb.is_empty())
-                    .map(|kb| format!("bindkey '{}' \"{}\"", kb, &provisioned_cmd)),
-            );
+        // Generate the lines for each bind
+        lines.extend(
+            fun.binds
+                .iter()
+                .filter(|kb| !included && !kb.is_empty())
+                .filter_map(|kb| render_bind(kb, &provisioned_cmd, self.keymap(global_config), global_config)),
+        );
+        lines
+    }
+
+    fn extra_table(&self, global_config: &GlobalConfig) -> Option<String> {
+        let mut lines: Vec<String> = Vec::new();
+        for fun in self.sorted_fns() {
+            // included actions keeping their namespace are already defined by their own plugin
+            if fun.namespace.is_some() {
+                continue;
+            }
+            let fun_lines = self.fun_lines(fun, global_config);
+            if !fun_lines.is_empty() {
+                lines.push(source_map(&fun.get_cmd(self, global_config), &fun.location, global_config));
+                lines.extend(fun_lines);
+            }
         }
-        lines.extend(self.binds.iter().map(|kb| {
-            format!(
-                "bindkey '{}' \"{}\"",
+        let mark = lines.len();
+        lines.extend(self.binds.iter().filter_map(|kb| {
+            render_bind(
+                kb,
+                &templatize_simple(self, &global_config.selector_widget_template, global_config),
+                self.keymap(global_config),
+                global_config
+            )
+        }));
+        lines.extend(self.menu_binds.iter().filter_map(|kb| {
+            render_bind(
                 kb,
-                templatize_simple(self, &global_config.selector_widget_template)
+                &templatize_simple(self, &global_config.menu_widget_template, global_config),
+                self.keymap(global_config),
+                global_config
             )
         }));
+        // the selector binds of a plugin are only set in config.toml
+        if lines.len() > mark {
+            lines.insert(mark, source_map(&format!("binds of {}", self.name), &Location::plugin(&self.name), global_config));
+        }
         if lines.is_empty() {
             None
         } else {
@@ -179,96 +296,66 @@ impl Plugin {
         }
     }
 
-    fn generated_filepath(&self, global_config: &GlobalConfig) -> PathBuf {
-        return self.path.join(&global_config.generated_file);
-    }
-    fn env_filepath(&self, global_config: &GlobalConfig) -> Option<PathBuf> {
-        let path = self.path.join(&global_config.provides_file);
-        if path.exists() {
-            Some(path)
-        } else {
-            None
-        }
-    }
-
-
-    // todo: kind of ugly that this needs a mut ref
-    fn write_generated_file(
-        &mut self,
-        contents: &str,
-        global_config: &GlobalConfig,
-    ) -> Result<(), io::Error> {
-        if global_config.generated_file.is_absolute() {
-            if global_config.generated_file.is_absolute() {
-                let mut file = std::fs::OpenOptions::new()
-                    .append(true)    // Only append to the file
-                    .open(&global_config.generated_file)?;
-        
-                use std::io::Write;
-                file.write_all(contents.as_bytes())?; // todo: link to source code
-        
-                debug!("Appended to {:#?}", global_config.generated_file);
-                debug!("Appended {:#?}", contents);
+    // The commands this plugin provides, symlinked, defined by its sources or by extra_table, and the aliases of its actions
+    fn completion_group(&self, global_config: &GlobalConfig) -> Option<completion::Group> {
+        let mut entries = Vec::new();
+        let mut completers = Vec::new();
+        for fun in self.sorted_fns() {
+            // included actions keeping their namespace are listed by their own plugin
+            if fun.namespace.is_some() || fun.flags.contains(&FnFlag::PG) {
+                continue;
             }
-        } else {
-            let file_path = self.generated_filepath(global_config);
-
-            std::fs::write(&file_path, contents)?;
-            self.sources.push(file_path.clone());
-
-            debug!("Generated {:#?}", file_path);
-            debug!("Generated {:#?}", contents);
-            compile_to_zwc(&file_path)?;
-        }
-        Ok(())
-    }
-
-    fn write_env_file<'a>(&self, global_config: &'a GlobalConfig) -> Result<(), io::Error> {
-        let mut file = None;
-        for fun in self.fns.values() {
-            if let Some(fstring) = &fun.fstring {
-                if file.is_none() {
-                    let file_path = &self.path.join(&global_config.provides_file);
-                    file = Some(File::create(&file_path)?);
-                    // todo: fix provision detection
-                    if let Some(ref mut file) = file {
-                        writeln!(file, "this={}", &self.get_alias_ref())?;
-                        writeln!(file, "this_name={}", self.name)?;
-                    }
+            let desc = fun.desc.clone().unwrap_or_default();
+            let provided = (fun.bin.is_some() && !fun.flags.contains(&FnFlag::WG)) || fun.fstring.is_some();
+            if provided || fun.does_provision() {
+                let cmd = fun.get_cmd(self, global_config);
+                if let Some(ref complete) = fun.complete {
+                    completers.push((cmd.clone(), complete.clone()));
                 }
-                if let Some(ref mut file) = file {
-                    writeln!(file, "{}={}", fstring, fun.get_cmd(self, global_config))?;
+                entries.push((cmd, desc.clone()));
+            }
+            if fun.included_from.is_none() {
+                if let Some(alias) = fun.alias.as_ref().filter(|alias| !alias.is_empty()) {
+                    entries.push((alias.clone(), desc));
                 }
             }
         }
-        // todo: delete obsolete env files? Shouldn't be an actual issue for now tho.
-        // todo: compile
-        Ok(())
+        if entries.is_empty() {
+            return None;
+        }
+        Some(completion::Group {
+            plugin: self.name.clone(),
+            header: self.desc.clone().unwrap_or_else(|| self.name.clone()),
+            entries,
+            completers,
+        })
     }
-    fn env_contents<'a>(&self, global_config: &'a GlobalConfig) -> String {
-        let mut contents = format!(
-            "this={} this_name={} \\\n",
-            &self.get_alias_ref(),
-            self.name
-        );
-        for fun in self.fns.values() {
+
+    fn generated_filepath(&self, global_config: &GlobalConfig) -> PathBuf {
+        self.path.join(&global_config.generated_file)
+    }
+    // this, this_name and the fstrings, set while sourcing the plugin
+    fn env(&self, global_config: &GlobalConfig) -> Vec<(String, String)> {
+        let mut env = vec![
+            ("this".to_string(), self.get_alias_ref().to_string()),
+            ("this_name".to_string(), self.name.clone()),
+        ];
+        for fun in self.sorted_fns() {
             if let Some(fstring) = &fun.fstring {
-                contents.push_str(&format!(
-                    "{}={} \\\n",
-                    fstring,
-                    fun.get_cmd(self, global_config)
-                ));
+                env.push((fstring.clone(), fun.get_cmd(self, global_config)));
             }
         }
-        contents
-        // todo: delete obsolete env files? Shouldn't be an actual issue for now tho.
-        // todo: compile
+        env
+    }
+
+    fn keymap(&self, global_config: &GlobalConfig) -> Option<Keymap> {
+        self.keymap.or(global_config.keymap)
     }
 
     // see plugin_from_dir
-    fn is_proper<'a>(&self) -> bool {
+    fn is_proper(&self) -> bool {
         if let Some(alias) = &self.alias {
-            alias != ""
+            !alias.is_empty()
         } else {
             true
         }
@@ -276,7 +363,7 @@ impl Plugin {
 }
 
 
-fn compile_to_zwc(file_path: &PathBuf) -> Result<(), io::Error> {
+fn compile_to_zwc(file_path: &Path) -> Result<(), io::Error> {
     let output = Command::new("zsh")
     .arg("-c")
     .arg(format!("zcompile {}", file_path.to_string_lossy()))
@@ -292,63 +379,193 @@ fn compile_to_zwc(file_path: &PathBuf) -> Result<(), io::Error> {
     Ok(())
 }
 
-fn write_generated_init_file<'a>(
-    scanned_plugins: &'a HashMap<String, Plugin>,
+fn render_init_file(
+    scanned_plugins: &HashMap<String, Plugin>,
     global_config: &GlobalConfig,
-) -> Result<(), CreationError> {
-    const FZS_INIT_ZSH: &[u8] = include_bytes!("../files/fzs_init.zsh");
-    let mut contents = String::from_utf8_lossy(FZS_INIT_ZSH).to_string();
+) -> Result<String, CreationError> {
+    let backend = global_config.backend();
     let mut sorted_plugins: Vec<&Plugin> = scanned_plugins.values()
     .filter(|pg| pg.is_proper())
     .collect();
     sorted_plugins.sort_by(|a, b| a.name.cmp(&b.name));
 
-    let mut replacements: HashMap<&str, Cow<'a, str>> = HashMap::new();
+    let mut replacements: BTreeMap<&str, minijinja::Value> = BTreeMap::new();
     let plugins_iter = sorted_plugins.into_iter();
-    replacements.insert("fn_table", build_plugin_table(plugins_iter.clone(), &global_config).into());
-    replacements.insert("all_fn_table", build_all_fn_table(plugins_iter, &global_config).into());
+    let fn_table = build_plugin_table(plugins_iter.clone(), global_config);
+    let all_fn_table = build_all_fn_table(plugins_iter, global_config);
+    // the plugin table starts with the plugin alias
+    replacements.insert("plugin_accept_args", global_config.accept.fzf_args(&fn_table, 1, backend).into());
+    let all_fn_field = accept::alias_field(&global_config.all_fn_table_template);
+    replacements.insert("all_fn_accept_args", global_config.accept.fzf_args(&all_fn_table, all_fn_field, backend).into());
+    // a template_file from before accept_args keeps the default auto-accept
+    let base_accept_args = if global_config.template_accepts {
+        String::new()
+    } else {
+        RawAccept::default().into_accept().fzf_args("", 1, backend)
+    };
+    replacements.insert("base_accept_args", base_accept_args.into());
+    replacements.insert("fn_table", backend.double_quoted_path(&fn_table).into());
+    replacements.insert("all_fn_table", backend.double_quoted_path(&all_fn_table).into());
 
-    contents = templatize_contents(contents, &global_config, &replacements)?;
+    let mut contents = templatize_contents(backend.init_template(), global_config, replacements)?;
     contents.push_str(
         &global_config.plugin_selector_binds.iter()
-            .map(|kb| {
-                format!(
-                    "bindkey '{}' \"{}\"\n",
-                    kb,
-                    format!("{}.plugin-select.wg", global_config.fzs_name)
-                )
-            })
+            .filter_map(|kb| render_bind(kb, &format!("{}.plugin-select.wg", global_config.fzs_name), global_config.keymap, global_config))
+            .map(|line| format!("{}\n", line))
             .collect::<String>()
     );
     contents.push_str(
         &global_config.all_fn_selector_binds.iter()
-            .map(|kb| {
-                format!(
-                    "bindkey '{}' \"{}\"\n",
-                    kb,
-                    format!("{}.all-fn-select.wg", global_config.fzs_name)
-                )
-            })
+            .filter_map(|kb| render_bind(kb, &format!("{}.all-fn-select.wg", global_config.fzs_name), global_config.keymap, global_config))
+            .map(|line| format!("{}\n", line))
             .collect::<String>()
     );
 
+    contents.push_str(&render_stale_check(global_config)?);
+
+    let mut sorted_plugins: Vec<&Plugin> = scanned_plugins.values().collect();
+    sorted_plugins.sort_by(|a, b| a.name.cmp(&b.name));
     contents.push_str(&build_source_commands(
-        scanned_plugins.values(),
-        &global_config,
+        sorted_plugins.into_iter(),
+        global_config,
     ));
+    if render_completion_file(scanned_plugins, global_config).is_some() {
+        let completion_file = format!(
+            "\"{}\"",
+            backend.double_quoted_path(&pathbuf_to_string(&completion_path(global_config), global_config))
+        );
+        contents.push_str(&backend.source(&[], &[completion_file]));
+    }
+    Ok(contents)
+}
+
+fn write_generated_init_file(
+    scanned_plugins: &HashMap<String, Plugin>,
+    global_config: &GlobalConfig,
+    quick: bool,
+) -> Result<(), CreationError> {
+    let contents = render_init_file(scanned_plugins, global_config)?;
+    if quick && is_up_to_date(&global_config.init_file, &contents, global_config) {
+        return Ok(());
+    }
     fs::write(&global_config.init_file, contents)?;
 
-    compile_to_zwc(&global_config.init_file)?;
+    global_config.backend().compile(&global_config.init_file)?;
     Ok(())
 }
 
+// Heads each block of a generated file, so an error in it can be traced to the file and line it came from, see
+// explain_generated_line
+const SOURCE_MAP_PREFIX: &str = "# fzs: ";
+
+fn source_map(target: &str, location: &Location, global_config: &GlobalConfig) -> String {
+    quote::table_field(&format!("{}{} <- {}", SOURCE_MAP_PREFIX, target, location.display(global_config)))
+}
+
+fn stamp_path(global_config: &GlobalConfig) -> PathBuf {
+    global_config.data_dir.join("fzs_build.stamp")
+}
+
+fn completion_path(global_config: &GlobalConfig) -> PathBuf {
+    global_config
+        .data_dir
+        .join(format!("fzs_completion.{}", global_config.backend().extension()))
+}
+
+// None if the shell has no generated completion, see Backend::completion
+fn render_completion_file(
+    scanned_plugins: &HashMap<String, Plugin>,
+    global_config: &GlobalConfig,
+) -> Option<String> {
+    let groups: Vec<completion::Group> = sorted_plugins(scanned_plugins)
+        .into_iter()
+        .filter_map(|pg| pg.completion_group(global_config))
+        .collect();
+    global_config.backend().completion(&groups, &global_config.fzs_name)
+}
+
+fn write_completion_file(
+    scanned_plugins: &HashMap<String, Plugin>,
+    global_config: &GlobalConfig,
+    quick: bool,
+) -> Result<(), io::Error> {
+    let Some(contents) = render_completion_file(scanned_plugins, global_config) else {
+        return Ok(());
+    };
+    let file_path = completion_path(global_config);
+    if quick && is_up_to_date(&file_path, &contents, global_config) {
+        return Ok(());
+    }
+    write_generated_file(&file_path, &contents, global_config)
+}
+
+// Guards the sourcing of plugin files against a build that is older than the plugins, see StaleCheck
+fn render_stale_check(global_config: &GlobalConfig) -> Result<String, io::Error> {
+    let fzs_bin = env::current_exe()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or("fzs".to_string());
+    let backend = global_config.backend();
+    let rebuild_cmd = format!(
+        "\"{}\" --config \"{}\" --root-dir \"$FZS_ROOT_DIR\" --data-dir \"$FZS_DATA_DIR\" build --quick",
+        backend.double_quoted(&fzs_bin),
+        backend.double_quoted_path(&pathbuf_to_string_basic(&global_config.config_file))
+    );
+    let on_stale = backend.on_stale(global_config.stale_check, &global_config.fzs_name, &rebuild_cmd);
+    if on_stale.is_empty() {
+        return Ok(String::new());
+    }
+    let ctx = minijinja::context! {
+        fzs_name => &global_config.fzs_name,
+        stamp_file => backend.double_quoted_path(&pathbuf_to_string_basic(&stamp_path(global_config))),
+        config_file => backend.double_quoted_path(&pathbuf_to_string_basic(&global_config.config_file)),
+        source_pattern => backend.source_pattern(&source_extensions(global_config)),
+        on_stale => on_stale,
+    };
+    global_config.templates.render(backend.stale_check_template(), ctx)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:#}", e)))
+}
+
+// The extensions of the files fzs parses and sources, bash_source_extensions only with bash
+fn source_extensions(global_config: &GlobalConfig) -> Vec<String> {
+    let mut extensions = global_config.source_extensions.clone();
+    if global_config.shell == Shell::Bash {
+        extensions.extend(global_config.bash_source_extensions.iter().cloned());
+    }
+    extensions
+}
+
+// An unchanged file with its .zwc in place needs neither a write nor a zcompile
+fn is_up_to_date(file_path: &Path, contents: &str, global_config: &GlobalConfig) -> bool {
+    fs::read_to_string(file_path).is_ok_and(|old| old == contents)
+        && (!global_config.backend().compiles() || zwc_path(file_path).exists())
+}
+
+// Invalid binds were already reported by check_binds
+fn render_bind(kb: &str, widget: &str, keymap: Option<Keymap>, global_config: &GlobalConfig) -> Option<String> {
+    keybind::translate(kb)
+        .ok()
+        .map(|sequence| global_config.backend().bind(&sequence, widget, keymap))
+}
+
+fn render_table(rows: Vec<table::Row>, global_config: &GlobalConfig) -> String {
+    table::render(rows, global_config.align_columns, global_config.column_colors)
+}
+
 fn build_all_fn_table<'a>(plugins: impl Iterator<Item = &'a Plugin>, global_config: &'a GlobalConfig) -> String {
-    let contents = plugins.flat_map(|pg| {
-        pg.fns.values().filter(|fun| !fun.flags.contains(&FnFlag::NA) && !fun.flags.contains(&FnFlag::PG)).map(move |fun| {
-            templatize(Some(fun), pg, &global_config.all_fn_table_template, &global_config, false, false)
-        })
+    let listed = plugins.flat_map(|pg| {
+        pg.sorted_fns().into_iter().filter(|fun| fun.included_from.is_none() && !fun.flags.contains(&FnFlag::NA) && !fun.flags.contains(&FnFlag::PG)).map(move |fun| (pg, fun))
+    }).collect();
+    let ranked = global_config.history.order(
+        listed,
+        global_config.sort,
+        |(pg, fun)| fun.get_cmd(pg, global_config),
+        |(_, fun)| fun.flags.contains(&FnFlag::TOP),
+    );
+    let contents = ranked.into_iter().map(|(pg, fun)| table::Row {
+        line: templatize(fun, pg, &global_config.all_fn_table_template, global_config, true),
+        category: table::Category::of(&fun.flags),
     });
-    contents.collect::<Vec<_>>().join("\n")
+    render_table(contents.collect(), global_config)
 }
 
 fn build_source_commands<'a>(
@@ -357,32 +574,21 @@ fn build_source_commands<'a>(
 ) -> String {
     let mut source_content = String::new();
     for plugin in plugins {
+        // bash_sources are only parsed with shell = "bash", see populate_dir
+        let bash_sources = if global_config.shell == Shell::Bash { &plugin.bash_sources[..] } else { &[] };
         let mut sources = plugin
             .sources
             .iter()
-            .map(|s| format!("\"{}\"", &pathbuf_to_string(s, global_config)))
+            .chain(bash_sources)
+            .map(|s| format!("\"{}\"", global_config.backend().double_quoted_path(&pathbuf_to_string(s, global_config))))
             .collect::<Vec<String>>();
         sources.sort();
 
         if !sources.is_empty() {
-            // todo: unsure if joining with cat is slower than calling source multiple times
-            if sources.len() > 1 {
-                source_content.push_str(&format!(
-                    "\n{}source <(cat {})\n",
-                    plugin.env_contents(global_config),
-                    sources.join(" ")
-                ));
-            } else {
-                source_content.push_str(&format!(
-                    "\n{}source {}\n",
-                    plugin.env_contents(global_config),
-                    sources.get(0).unwrap()
-                ));
-            }
-
+            source_content.push_str(&global_config.backend().source(&plugin.env(global_config), &sources));
         }
     }
-    source_content.push_str("this(){echo ${${funcstack[2]}%%.*};}\n");
+    source_content.push_str(global_config.backend().this_fn());
     
     // source_content.push_str(&"\nunset fzs.temp");
     source_content
@@ -392,22 +598,23 @@ fn build_plugin_table<'a>(
     plugins: impl Iterator<Item = &'a Plugin>,
     global_config: &'a GlobalConfig,
 ) -> String {
-    plugins
-        .map(|pg| {
-            format!(
+    let rows = plugins
+        .map(|pg| table::Row {
+            line: format!(
                 "{}\t{}\t{}\t{}\t{}",
-                pg.get_alias_or_space(),
-                pathbuf_to_string(&pg.path, global_config),
-                templatize_simple(pg, &global_config.selector_widget_template),
-                pg.name,
-                pg.get_desc_ref().unwrap_or("")
-            )
+                quote::table_field(pg.get_alias_or_space()),
+                quote::table_field(&pathbuf_to_string(&pg.path, global_config)),
+                quote::table_field(&templatize_simple(pg, &global_config.selector_widget_template, global_config)),
+                quote::table_field(&pg.name),
+                quote::table_field(pg.get_desc_ref().unwrap_or(""))
+            ),
+            category: Some(table::Category::Plugin),
         })
-        .collect::<Vec<String>>()
-        .join("\n")
+        .collect();
+    render_table(rows, global_config)
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone)]
 struct Fun {
     name: String,
     bin: Option<PathBuf>,
@@ -422,10 +629,27 @@ struct Fun {
     binds: Keybinds,
     #[serde(default)]
     fstring: Option<String>,
+    #[serde(skip)]
+    included_from: Option<String>, // set by map_includes
+    #[serde(skip)]
+    namespace: Option<String>, // replaces the plugin alias in templates, see IncludeNamespace
+    #[serde(skip)]
+    location: Location, // where the action was declared, for diagnostics
+    #[serde(default)]
+    key: Option<String>, // the menu key asked for with key=, see Plugin::assign_menu_keys
+    #[serde(skip)]
+    menu_key: Option<String>, // the menu key it got
+    #[serde(default, rename = "override")]
+    overrides: bool, // its binds may shadow default widgets, see keybind::Registry
+    #[serde(default)]
+    complete: Option<String>, // the argument completer asked for with complete=, see completion::zsh
+    #[serde(skip)]
+    provenance: Provenance, // where its fields were set, see fzs explain
 }
 
 // todo: safer flags
-#[derive(Debug, Deserialize, PartialEq, Clone, Eq, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, Eq, Hash)]
 enum FnFlag {
     WG, // Widget: Just makes selector invoke with zle. special as it doesn't override capability
     WR, // transforms target into a widget on shell and runs it
@@ -446,6 +670,7 @@ enum FnFlag {
         // ForceSymlink?
     AL, // alias
     NN, // NoNamespace
+    TOP, // Pinned to the top of the selectors
 }
 
 // todo: fancier way?
@@ -471,6 +696,7 @@ impl fmt::Display for FnFlag {
             FnFlag::CMD => "CMD",
             FnFlag::AL => "AL",
             FnFlag::NN => "NN",
+            FnFlag::TOP => "TOP",
         };
         write!(f, "{}", s)
     }
@@ -486,7 +712,7 @@ impl FromStr for FnFlag {
             "WJR" => Ok(FnFlag::WJR),
             "WJSUB" => Ok(FnFlag::WJSUB),
             "PG" => Ok(FnFlag::PG),
-            "PGI" => Ok(FnFlag::PFN),
+            "PGI" => Ok(FnFlag::PGI),
             "PFN" => Ok(FnFlag::PFN),
             "PBG" => Ok(FnFlag::PBG),
             "SS" => Ok(FnFlag::SS),
@@ -498,6 +724,7 @@ impl FromStr for FnFlag {
             "CMD" => Ok(FnFlag::CMD),
             "AL" => Ok(FnFlag::AL),
             "NN" => Ok(FnFlag::NN),
+            "TOP" => Ok(FnFlag::TOP),
             _ => Err(()),
         }
     }
@@ -515,25 +742,73 @@ impl FnFlag {
 
 
 impl Fun {
-    fn merge_from(&mut self, other: Fun) {
+    fn merge_from(&mut self, other: Fun, diagnostics: &mut Diagnostics) {
+        let location = other.location.clone();
+        let flags = sorted_flags(&other);
         // Claude suggests self.alias = other.alias.clone().or(self.alias.take());
         if let Some(alias) = other.alias {
+            self.provenance.record("alias", Op::Set, &alias, Layer::Config, &location);
             self.alias = Some(alias);
         }
         if let Some(desc) = other.desc {
+            self.provenance.record("desc", Op::Set, &desc, Layer::Config, &location);
             self.desc = Some(desc);
         }
 
+        if self.flags != other.flags {
+            self.provenance.record("flags", Op::Set, flags, Layer::Config, &location);
+        }
         self.flags = other.flags;
+        if !other.binds.is_empty() {
+            self.provenance.record("binds", Op::Set, other.binds.join(" "), Layer::Config, &location);
+            self.binds = other.binds;
+        }
+        if let Some(key) = other.key {
+            self.provenance.record("key", Op::Set, &key, Layer::Config, &location);
+            self.key = Some(key);
+        }
+        if let Some(complete) = other.complete {
+            self.provenance.record("complete", Op::Set, &complete, Layer::Config, &location);
+            self.complete = Some(complete);
+        }
+        if other.overrides {
+            self.provenance.record("override", Op::Set, "true", Layer::Config, &location);
+        }
+        self.overrides |= other.overrides;
         if let Some(cmd) = other.cmd {
-            warn!("cmd '{}' cannot be set on an existing Fn {}!", cmd, self.name)
+            diagnostics.warn(&other.location, format!("cmd '{}' cannot be set on an existing Fn {}!", cmd, self.name))
         }
     }
 
-    fn is_shell_function(&self) -> bool {
-        self.flags
-        .iter()
-        .any(|item| item.to_string().starts_with("W") || item == &FnFlag::PG || item == &FnFlag::PBG )
+    // Records the fields an action was scanned or declared with. Flags in defaults come from the directory or source
+    // rather than the action itself.
+    fn record_scanned(&mut self, layer: Layer, defaults: &FnFlags) {
+        let location = self.location.clone();
+        let provenance = &mut self.provenance;
+        provenance.record("name", Op::Set, &self.name, layer, &location);
+        for (field, value) in [
+            ("alias", &self.alias),
+            ("desc", &self.desc),
+            ("cmd", &self.cmd),
+            ("key", &self.key),
+            ("complete", &self.complete),
+        ] {
+            if let Some(value) = value {
+                provenance.record(field, Op::Set, value, layer, &location);
+            }
+        }
+        let mut flags: Vec<&FnFlag> = self.flags.iter().collect();
+        flags.sort_by_key(|flag| flag.to_string());
+        for flag in flags {
+            let layer = if defaults.contains(flag) { Layer::Default } else { layer };
+            provenance.record("flags", Op::Add, flag.to_string(), layer, &location);
+        }
+        if !self.binds.is_empty() {
+            provenance.record("binds", Op::Set, self.binds.join(" "), layer, &location);
+        }
+        if self.overrides {
+            provenance.record("override", Op::Set, "true", layer, &location);
+        }
     }
 
     fn does_provision(&self) -> bool {
@@ -552,44 +827,55 @@ impl Fun {
         Ok(())
     }
 
-    fn check(&mut self) -> Result<(), ScanningError> {
+    fn check(&mut self, diagnostics: &mut Diagnostics) -> Result<(), ScanningError> {
         // self.is_shell_function() && !self.does_provision() && self.check_cmd_whitespace();
         let mut selector_flag = None;
         let mut is_widget = false;
         // responsible for ensuring sane run behavior when selected in plugin-selector
         for flag in self.flags.iter() {
-            if flag == &FnFlag::PG {
+            if flag == &FnFlag::PGI {
+                if self.alias.is_some() || self.cmd.is_some() {
+                    diagnostics.warn(&self.location, format!("alias and cmd cannot be set on the included plugin {}, removing", self.name));
+                    self.provenance.record("alias", Op::Unset, "", Layer::Check, &self.location);
+                    self.provenance.record("cmd", Op::Unset, "", Layer::Check, &self.location);
+                    self.alias = None;
+                    self.cmd = None;
+                }
+            } else if flag == &FnFlag::PG {
                 if self.alias.is_some() {
-                    warn!("Aliases cannot be set on the plugin {}, removing", self.name);
+                    diagnostics.warn(&self.location, format!("Aliases cannot be set on the plugin {}, removing", self.name));
+                    self.provenance.record("alias", Op::Unset, "", Layer::Check, &self.location);
                     self.alias = None;
                 }
                 if self.cmd.is_some() {
-                    warn!("cmd cannot be set on the plugin {}, removing", self.name);
+                    diagnostics.warn(&self.location, format!("cmd cannot be set on the plugin {}, removing", self.name));
+                    self.provenance.record("cmd", Op::Unset, "", Layer::Check, &self.location);
                     self.cmd = None;
                 }
                 selector_flag = Some(flag.clone());
                 is_widget = true;
             } else if flag == &FnFlag::NR {
                 if let Some(selector_flag) = selector_flag {
-                    warn!("Function {} already has the run flag {} but another run flag {} was found, removing the new one.", self.name, selector_flag, flag);
+                    diagnostics.warn(&self.location, format!("Function {} already has the run flag {} but another run flag {} was found, removing the new one.", self.name, selector_flag, flag));
                 }
                 selector_flag = Some(flag.clone());
             } else if flag.to_string().starts_with("W") {
                 if let Some(selector_flag) = selector_flag {
-                    warn!("Function {} already has the run flag {} but another run flag {} was found, removing the new one.", self.name, selector_flag, flag);
+                    diagnostics.warn(&self.location, format!("Function {} already has the run flag {} but another run flag {} was found, removing the new one.", self.name, selector_flag, flag));
                 }
                 if flag == &FnFlag::WG { self.check_cmd_whitespace()?; }; // widget is unique in that it is called with zle, can have a command, and is not provisioned
                 is_widget = true;
                 selector_flag = Some(flag.clone());
             } else if flag == &FnFlag::SS {
                 if let Some(selector_flag) = selector_flag {
-                    warn!("Function {} already has the run flag {} but another run flag {} was found, removing the new one.", self.name, selector_flag, flag);
+                    diagnostics.warn(&self.location, format!("Function {} already has the run flag {} but another run flag {} was found, removing the new one.", self.name, selector_flag, flag));
                 }
                 selector_flag = Some(flag.clone());
             }
         }
         if !is_widget && !self.binds.is_empty() {
             self.flags.insert(FnFlag::WG);
+            self.provenance.record("flags", Op::Add, "WG", Layer::Check, &self.location);
             info!("Treating {} as widget (WG) due to binds", self.name);
         }
         Ok(())
@@ -599,25 +885,17 @@ impl Fun {
         if pg.name == "base" || self.flags.contains(&FnFlag::NN) {
             self.name.clone()
         } else if self.flags.contains(&FnFlag::PG) {
-            templatize_simple(self, &global_config.selector_widget_template)
+            templatize_simple(self, &global_config.selector_widget_template, global_config)
         } else if self.does_provision() {
                     templatize(
-                        self.into(),
+                        self,
                         pg,
-                        &global_config.fn_template,
+                        pg.fn_template.as_deref().unwrap_or(&global_config.fn_template),
                         global_config,
-                        true,
                         false
                     )
         } else {
-            templatize(
-                self.into(),
-                pg,
-                self.cmd.as_deref().unwrap_or(&global_config.fn_template), 
-                global_config,
-                true,
-                false
-            )
+            self.get_real_cmd(pg, global_config)
         }
     }
 
@@ -625,14 +903,15 @@ impl Fun {
         if pg.name == "base" || self.flags.contains(&FnFlag::NN) {
             self.name.clone()
         } else if self.flags.contains(&FnFlag::PG) {
-            templatize_simple(self, &global_config.selector_widget_template)
+            templatize_simple(self, &global_config.selector_widget_template, global_config)
+        } else if let Some(cmd) = &self.cmd {
+            templatize_cmd(cmd, self, pg)
         } else {
             templatize(
-                self.into(),
+                self,
                 pg,
-                self.cmd.as_deref().unwrap_or(&global_config.fn_template), 
+                pg.fn_template.as_deref().unwrap_or(&global_config.fn_template),
                 global_config,
-                true,
                 false
             )
         }
@@ -647,62 +926,65 @@ impl Fun {
     }
 }
 
-fn templatize_simple<T: Initial>(item: &T, s: &str) -> String {
-    s.to_string()
-        .replace("{{ name }}", item.get_name_ref())
-        .replace("{{ alias }}", item.get_alias_ref())
-        .replace("{{ desc }}", item.get_desc_ref().unwrap_or_default())
+// Name templates render infallibly once validated by Templates::add, a failure is logged and leaves the template as is
+fn render_template(s: &str, ctx: minijinja::Value, global_config: &GlobalConfig) -> String {
+    global_config.templates.render(s, ctx).unwrap_or_else(|e| {
+        error!("Failed to render {:?}: {:#}", s, e);
+        s.to_string()
+    })
+}
+
+// see template::NAME_VARS
+fn templatize_simple<T: Initial>(item: &T, s: &str, global_config: &GlobalConfig) -> String {
+    let ctx = minijinja::context! {
+        name => item.get_name_ref(),
+        alias => item.get_alias_ref(),
+        desc => item.get_desc_ref().unwrap_or_default(),
+    };
+    render_template(s, ctx, global_config)
+}
+
+fn flags_value(fun: &Fun) -> minijinja::Value {
+    let mut flags: Vec<String> = fun.flags.iter().map(|flag| flag.to_string()).collect();
+    flags.sort();
+    minijinja::Value::from_object(template::Flags(flags))
 }
 
+// fn_template, or with table a row of fn_table_template or all_fn_table_template. See template::FN_VARS and TABLE_VARS.
 fn templatize(
-    fun: Option<&Fun>,
+    fun: &Fun,
     pg: &Plugin,
     s: &str,
     global_config: &GlobalConfig,
-    simple: bool,
-    alias_or_space: bool
+    table: bool
 ) -> String {
-    let mut res = s.to_string();
-
-    let pg_alias = if alias_or_space {
-        pg.get_alias_or_space()
-    } else {
-        pg.get_alias_ref()
+    let pg_alias = fun.namespace.as_deref().unwrap_or(pg.get_alias_ref());
+    let field = |value: &str| if table { quote::table_field(value) } else { value.to_string() };
+    let mut ctx = minijinja::context! {
+        pg_name => field(&pg.name),
+        pg_alias => field(pg_alias),
+        name => field(&fun.name),
+        alias => field(fun.get_alias_ref()),
+        desc => field(fun.desc.as_deref().unwrap_or("")),
+        flags => flags_value(fun),
     };
-    
-    res = res
-        .replace("{{ pg_name }}", &pg.name)
-        .replace("{{ pg_alias }}", &pg_alias);
-    
-    if let Some(fun) = fun {
-        let fun_alias = if alias_or_space {
-            fun.get_alias_or_space()
-        } else {
-            fun.get_alias_ref()
+    if table {
+        ctx = minijinja::context! {
+            cmds => field(&fun.get_cmd(pg, global_config)),
+            key => field(fun.menu_key.as_deref().unwrap_or("")),
+            ..ctx
         };
-    
-        res = res
-            .replace("{{ name }}", &fun.name)
-            .replace("{{ alias }}", &fun_alias)
-            .replace("{{ desc }}", &fun.desc.as_deref().unwrap_or(""));
-
-        if !simple {
-            res = res
-                .replace("{{ cmds }}", &fun.get_cmd(pg, global_config))
-                .replace(
-                    "{{ flags }}",
-                    &format!(
-                        ",{},",
-                        fun.flags
-                            .iter()
-                            .map(|flag| flag.to_string())
-                            .collect::<Vec<String>>()
-                            .join(",")
-                    ),
-                );
-        }
     }
-    res
+    render_template(s, ctx, global_config)
+}
+
+// cmds are shell code rather than templates, only the plain placeholders of fn_template are substituted
+fn templatize_cmd(cmd: &str, fun: &Fun, pg: &Plugin) -> String {
+    cmd.replace("{{ pg_name }}", &pg.name)
+        .replace("{{ pg_alias }}", fun.namespace.as_deref().unwrap_or(pg.get_alias_ref()))
+        .replace("{{ name }}", &fun.name)
+        .replace("{{ alias }}", fun.get_alias_ref())
+        .replace("{{ desc }}", fun.desc.as_deref().unwrap_or(""))
 }
 
 type Keybinds = Vec<String>;
@@ -740,8 +1022,6 @@ trait Initial {
     fn get_alias(&self) -> String;
     fn get_alias_ref(&self) -> &str;
     fn get_alias_or_space(&self) -> &str;
-    fn get_name(&self) -> String;
-    fn get_desc(&self) -> Option<String>;
     fn get_name_ref(&self) -> &String;
     fn get_desc_ref(&self) -> Option<&str>;
 }
@@ -768,12 +1048,6 @@ macro_rules! impl_initial {
                     self.alias.clone().unwrap()
                 }
             }
-            fn get_name(&self) -> String {
-                self.name.clone()
-            }
-            fn get_desc(&self) -> Option<String> {
-                self.desc.clone()
-            }
             fn get_name_ref(&self) -> &String {
                 &self.name
             }
@@ -789,17 +1063,24 @@ impl_initial!(Plugin);
 // impl_get_alias_with_fallback!(Wg, name, String);
 
 impl InitialPlugin {
-    fn to_plugin<'a>(self, fns: HashMap<String, Fun>) -> Plugin {
+    fn into_plugin(self, fns: HashMap<String, Fun>) -> Plugin {
         Plugin {
             alias: self.alias,
             name: self.name,
             desc: self.desc,
             path: self.path,
             sources: Vec::new(),
+            bash_sources: Vec::new(),
             fns,
             fn_template: None,
             fn_table_template: None,
             binds: Keybinds::new(),
+            menu_binds: Keybinds::new(),
+            overrides: false,
+            keymap: None,
+            include_namespace: None,
+            sort: None,
+            accept: RawAccept::default(),
         }
     }
 }
@@ -816,34 +1097,87 @@ struct GlobalConfig {
     name_from_cmd_regex: Regex,
     name_from_alias_template: String,
     selector_widget_template: String,
+    menu_widget_template: String,
     fn_template: String,
     fn_table_template: String,
     all_fn_table_template: String,
-    name_when_widget_template: String, // Unimplemented: probably we don't want this, rely on user to consistently name widgets
     template_file: PathBuf,
+    template: String, // the contents of template_file, or the default until a build writes it
     init_file: PathBuf,
     fzs_name: String,
     generated_file: PathBuf,
     provides_file: PathBuf,
     plugin_selector_binds: Keybinds,
     all_fn_selector_binds: Keybinds,
+    overrides: bool, // the selector binds may shadow default widgets
+    keymap: Option<Keymap>, // of all binds, see keybind::Keymap
     fzs_fzf_dir_cmd: String,
     fzs_fzf_pager_cmd: String,
     fzs_fzf_base_preview: String,
+    include_namespace: IncludeNamespace,
+    stale_check: StaleCheck,
+    config_file: PathBuf, // the config.toml that was loaded, see load_config
+    shell: Shell,
+    templates: Templates,
+    align_columns: bool,
+    column_colors: bool,
+    sort: Sort,
+    history: History, // of the selections, loaded with the config so that every build ranks by the latest one
+    accept: Accept,
+    template_accepts: bool, // template_file passes accept_args, else _base-select.wg adds the default ones
+    source_extensions: Vec<String>, // of the non-executable files in a plugin directory that are parsed and sourced
+    bash_source_extensions: Vec<String>, // the same, but only when shell is bash
+}
+
+impl GlobalConfig {
+    fn backend(&self) -> &'static dyn Backend {
+        self.shell.backend()
+    }
+
+    // The directories a build writes to, and the default template_file. Loading the config leaves the filesystem
+    // alone, so that build --dry-run and check write nothing.
+    fn create_dirs(&self) -> Result<(), io::Error> {
+        for (setting, dir) in [("root_dir", &self.root_dir), ("path_dir", &self.path_dir), ("data_dir", &self.data_dir)] {
+            fs::create_dir_all(dir).map_err(|e| {
+                io::Error::new(io::ErrorKind::PermissionDenied, format!("Cannot create {}: {}", setting, e))
+            })?;
+        }
+        if !self.template_file.exists() {
+            fs::write(&self.template_file, &self.template)?;
+        }
+        Ok(())
+    }
+
+    // Compiles every template up front, so that a syntax error or an unknown variable fails loading the config
+    fn add_templates(&mut self) -> Result<(), io::Error> {
+        let selector_vars = [template::SELECTOR_VARS, template::FILE_VARS].concat();
+        let init_vars = [template::INIT_VARS, template::FILE_VARS].concat();
+        let template_file = self.template.clone();
+        let templates = [
+            ("fn_template", self.fn_template.clone(), template::FN_VARS),
+            ("fn_table_template", self.fn_table_template.clone(), template::TABLE_VARS),
+            ("all_fn_table_template", self.all_fn_table_template.clone(), template::TABLE_VARS),
+            ("selector_widget_template", self.selector_widget_template.clone(), template::NAME_VARS),
+            ("menu_widget_template", self.menu_widget_template.clone(), template::NAME_VARS),
+            ("name_from_alias_template", self.name_from_alias_template.clone(), template::ALIAS_VARS),
+            ("template_file", template_file.clone(), &selector_vars),
+            ("the init file template", self.backend().init_template().to_string(), &init_vars),
+            ("the stale check template", self.backend().stale_check_template().to_string(), template::STALE_CHECK_VARS),
+        ];
+        for (setting, source, vars) in templates {
+            self.templates.add(&source, vars).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("Invalid {}: {:#}", setting, e))
+            })?;
+        }
+        self.template_accepts = self.templates.uses(&template_file, "accept_args");
+        Ok(())
+    }
 }
 
 fn default_root_dir() -> Result<PathBuf, io::Error> {
     let home_dir = env::var("HOME").map_err(|e| io::Error::new(io::ErrorKind::NotFound, e))?;
     let root_dir = Path::new(&home_dir).join(".fzs");
 
-    if !root_dir.exists() {
-        fs::create_dir_all(&root_dir).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to create directory: {}", e),
-            )
-        })?;
-    }
     Ok(root_dir)
 }
 
@@ -853,14 +1187,6 @@ fn default_path_dir() -> Result<PathBuf, io::Error> {
 
     let path_dir = Path::new(&state_home).join("fzs");
 
-    if !path_dir.exists() {
-        fs::create_dir_all(&path_dir).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to create state directory: {}", e),
-            )
-        })?;
-    }
     Ok(path_dir)
 }
 
@@ -874,14 +1200,6 @@ fn config_dir(from: Option<String>) -> Result<PathBuf, io::Error> {
         }
     };
 
-    if !config_home.exists() {
-        fs::create_dir_all(&config_home).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to create config directory: {}", e),
-            )
-        })?;
-    }
     Ok(config_home)
 }
 
@@ -895,14 +1213,6 @@ fn data_dir(from: Option<String>) -> Result<PathBuf, io::Error> {
         }
     };
 
-    if !data_home.exists() {
-        fs::create_dir_all(&data_home).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to create config directory: {}", e),
-            )
-        })?;
-    }
     Ok(data_home)
 }
 
@@ -925,38 +1235,60 @@ struct RawGlobalConfig {
     name_from_cmd_regex_str: Option<String>,
     name_from_alias_template: Option<String>,
     selector_widget_template: Option<String>,
+    menu_widget_template: Option<String>,
     fn_template: Option<String>,
     fn_table_template: Option<String>,
     all_fn_table_template: Option<String>,
-    name_when_widget_template: Option<String>,
     fzs_name: Option<String>,
     generated_file: Option<String>,
     provides_file: Option<String>,
     #[serde(default)]
     plugin_selector_binds: Option<Keybinds>,
     all_fn_selector_binds: Option<Keybinds>,
+    #[serde(rename = "override")]
+    overrides: Option<bool>,
+    keymap: Option<Keymap>,
     fzf_dir_cmd: Option<String>,
     fzf_pager_cmd: Option<String>,
-    fzf_base_preview: Option<String>
+    fzf_base_preview: Option<String>,
+    include_namespace: Option<IncludeNamespace>,
+    stale_check: Option<StaleCheck>,
+    shell: Option<Shell>,
+    align_columns: Option<bool>,
+    column_colors: Option<bool>,
+    sort: Option<Sort>,
+    #[serde(default)]
+    accept: RawAccept,
+    source_extensions: Option<Vec<String>>,
+    bash_source_extensions: Option<Vec<String>>,
+}
+
+// These shadow history-search-backward and forward-word on purpose, see check_binds
+const DEFAULT_PLUGIN_SELECTOR_BIND: &str = "^[p";
+const DEFAULT_ALL_FN_SELECTOR_BIND: &str = "^[f";
+
+fn config_plugin_dir(global_config: &GlobalConfig) -> PathBuf {
+    global_config.data_dir.join("plugins")
 }
 
 fn string_to_pathbuf(path: &str) -> PathBuf {
     PathBuf::from(path.replace("$HOME", &env::var("HOME").unwrap_or("$HOME".to_string())))
 }
 
-fn pathbuf_to_string(path: &PathBuf, global_config: &GlobalConfig) -> String {
+fn pathbuf_to_string(path: &Path, global_config: &GlobalConfig) -> String {
     path.to_string_lossy()
-        .replace(&global_config.path_dir.to_string_lossy().as_ref(), "$FZS_PATH_DIR")
-        .replace(&global_config.root_dir.to_string_lossy().as_ref(), "$FZS_ROOT_DIR")
+        .replace(global_config.path_dir.to_string_lossy().as_ref(), "$FZS_PATH_DIR")
+        .replace(global_config.root_dir.to_string_lossy().as_ref(), "$FZS_ROOT_DIR")
         .replace(&env::var("HOME").unwrap_or("$HOME".to_string()), "$HOME")
 }
 
-fn pathbuf_to_string_basic(path: &PathBuf) -> String {
+fn pathbuf_to_string_basic(path: &Path) -> String {
     path.to_string_lossy()
         .replace(&env::var("HOME").unwrap_or("$HOME".to_string()), "$HOME")
 }
 
 impl RawGlobalConfig {
+    #[allow(clippy::wrong_self_convention)]
     fn to_global_config(self, config_dir: PathBuf) -> Result<GlobalConfig, io::Error> {
         let plugin_regex = Regex::new(&self.plugin_regex_str.unwrap_or(
             r"^([a-zA-Z0-9]+)(?:_([a-zA-Z0-9-]+))?(?:_([a-zA-Z0-9-]+))?_select$".to_string(),
@@ -984,16 +1316,8 @@ impl RawGlobalConfig {
         let root_dir = match self.root_dir {
             Some(dir) => {
                 let path = string_to_pathbuf(&dir);
-                if !path.exists() {
-                    fs::create_dir_all(&path).map_err(|e| {
-                        io::Error::new(
-                            io::ErrorKind::PermissionDenied,
-                            format!("Cannot create root_dir: {}", e),
-                        )
-                    })?;
-                }
                 // !path.metadata()?.permissions().readonly() doesn't work as expected
-                if !path.is_dir() {
+                if path.exists() && !path.is_dir() {
                     Err(io::Error::new(
                         io::ErrorKind::PermissionDenied,
                         "root_dir is not writable or accessible",
@@ -1007,15 +1331,7 @@ impl RawGlobalConfig {
         let path_dir = match self.path_dir {
             Some(dir) => {
                 let path = string_to_pathbuf(&dir);
-                if !path.exists() {
-                    fs::create_dir_all(&path).map_err(|e| {
-                        io::Error::new(
-                            io::ErrorKind::PermissionDenied,
-                            format!("Cannot create path_dir: {}", e),
-                        )
-                    })?;
-                }
-                if !path.is_dir() || !path.metadata()?.permissions().readonly() {
+                if path.exists() && !path.is_dir() {
                     Err(io::Error::new(
                         io::ErrorKind::PermissionDenied,
                         "path_dir is not writable or accessible",
@@ -1033,9 +1349,12 @@ impl RawGlobalConfig {
         };
 
 
+        let shell = self.shell.unwrap_or_default();
+        let extension = shell.backend().extension();
+
         let generated_file = match self.generated_file {
             Some(path) => string_to_pathbuf(&path),
-            None => data_dir.join("fzs_plugins.zsh"), // Absolute path, recommend using uncommon extension like zsht if using relative paths
+            None => data_dir.join(format!("fzs_plugins.{}", extension)), // Absolute path, recommend using uncommon extension like zsht if using relative paths
         };
 
         let provides_file = match self.provides_file {
@@ -1043,15 +1362,16 @@ impl RawGlobalConfig {
             None => PathBuf::from("fzs.env"),
         };
 
-        let template_file = config_dir.join("template.zsh");
+        let template_file = config_dir.join(format!("template.{}", extension));
 
         let fzs_fzf_base_preview = self.fzf_base_preview.unwrap_or("source $fzs_init_file > /dev/null 2>&1; source $fzs_plugins_file > /dev/null 2>&1; which -a {3}".to_string());
 
-        let plugin_selector_binds = self.plugin_selector_binds.unwrap_or(vec!["^[p".to_string()]);
-        let all_fn_selector_binds = self.all_fn_selector_binds.unwrap_or(vec!["^[f".to_string()]);
+        let plugin_selector_binds = self.plugin_selector_binds.unwrap_or(vec![DEFAULT_PLUGIN_SELECTOR_BIND.to_string()]);
+        let all_fn_selector_binds = self.all_fn_selector_binds.unwrap_or(vec![DEFAULT_ALL_FN_SELECTOR_BIND.to_string()]);
 
-        let init_file = data_dir.join("fzs_init.zsh");
-        let gc = GlobalConfig {
+        let init_file = data_dir.join(format!("fzs_init.{}", extension));
+        let config_file = config_dir.join("config.toml");
+        let mut gc = GlobalConfig {
             root_dir,
             path_dir,
             config_dir,
@@ -1064,18 +1384,18 @@ impl RawGlobalConfig {
             selector_widget_template: self
                 .selector_widget_template
                 .unwrap_or("{{ alias }}._select.wg".to_string()),
+            menu_widget_template: self
+                .menu_widget_template
+                .unwrap_or("{{ alias }}._menu.wg".to_string()),
             fn_template: self
                 .fn_template
                 .unwrap_or("{{ pg_alias }}.{{ name }}".to_string()),
-            name_when_widget_template: self
-                .name_when_widget_template
-                .unwrap_or("{{ name }}.wg".to_string()),
             fn_table_template: self
                 .fn_table_template
-                .unwrap_or("{{ name }}	{{ flags }}	{{ cmds }}	{{ desc }}".to_string()),
+                .unwrap_or(template::FN_TABLE_TEMPLATE.to_string()),
             all_fn_table_template: self
                 .all_fn_table_template
-                .unwrap_or("{{ pg_alias }}		{{ cmds }}	{{ name }}		{{ alias }}		{{ desc }}".to_string()),
+                .unwrap_or(template::ALL_FN_TABLE_TEMPLATE.to_string()),
             template_file,
             init_file,
             fzs_name: self.fzs_name.unwrap_or("fzs".to_string()),
@@ -1083,15 +1403,33 @@ impl RawGlobalConfig {
             provides_file,
             plugin_selector_binds,
             all_fn_selector_binds,
+            overrides: self.overrides.unwrap_or(false),
+            keymap: self.keymap,
             fzs_fzf_dir_cmd: self.fzf_dir_cmd.unwrap_or("ls -la".to_string()),
             fzs_fzf_pager_cmd: self.fzf_pager_cmd.unwrap_or("less -RX".to_string()),
-            fzs_fzf_base_preview
+            fzs_fzf_base_preview,
+            include_namespace: self.include_namespace.unwrap_or_default(),
+            stale_check: self.stale_check.unwrap_or_default(),
+            config_file,
+            shell,
+            templates: Templates::new(shell),
+            align_columns: self.align_columns.unwrap_or(true),
+            column_colors: self.column_colors.unwrap_or(false),
+            sort: self.sort.unwrap_or_default(),
+            history: History::default(),
+            accept: self.accept.into_accept(),
+            template: String::new(),
+            template_accepts: true,
+            source_extensions: self.source_extensions.unwrap_or(vec!["zshrc".to_string()]),
+            bash_source_extensions: self.bash_source_extensions.unwrap_or(vec!["bash".to_string()]),
         };
-
-        const TEMPLATE_ZSH: &[u8] = include_bytes!("../files/template.zsh");
-        if !gc.template_file.exists() {
-            fs::write(&gc.template_file, TEMPLATE_ZSH)?;
-        }
+        gc.history = History::load(&gc);
+        gc.template = match fs::read_to_string(&gc.template_file) {
+            Ok(template) => template,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => gc.backend().selector_template().to_string(),
+            Err(e) => return Err(e),
+        };
+        gc.add_templates()?;
 
         Ok(gc)
     }
@@ -1105,16 +1443,45 @@ struct RawPlugin {
     name: String,
     desc: Option<String>,
     alias: Option<String>,
-    #[serde(default)]
+    #[serde(default, alias = "functions")]
     fns: Vec<Fun>,
     // these last fields are wrapped with Option to allow default from Global when parsed into Plugin
     fn_template: Option<String>,
     #[serde(default)]
     binds: Keybinds,
+    #[serde(default)]
+    menu_binds: Keybinds,
+    #[serde(default, rename = "override")]
+    overrides: bool,
+    keymap: Option<Keymap>,
+    include_namespace: Option<IncludeNamespace>,
+    sort: Option<Sort>,
+    #[serde(default)]
+    accept: RawAccept,
 }
 
 impl Plugin {
-    fn merge_from_raw(&mut self, raw_plugin: RawPlugin) -> Result<(), ScanningError> {
+    // Plugins that only exist in config.toml live under data_dir, which is where their generated file goes if generated_file is relative.
+    fn from_raw(raw_plugin: RawPlugin, global_config: &GlobalConfig, diagnostics: &mut Diagnostics) -> Result<Plugin, ScanningError> {
+        let mut plugin = InitialPlugin {
+            name: raw_plugin.name.clone(),
+            path: config_plugin_dir(global_config).join(&raw_plugin.name),
+            alias: None,
+            desc: None,
+        }
+        .into_plugin(HashMap::new());
+        plugin.merge_from_raw(raw_plugin, diagnostics)?;
+
+        // without a file to symlink, actions built from a cmd are provided as shell functions under fn_template
+        for fun in plugin.fns.values_mut() {
+            if fun.cmd.is_some() && fun.bin.is_none() && !fun.is_widget() && !fun.does_provision() {
+                fun.flags.insert(FnFlag::PFN);
+            }
+        }
+        Ok(plugin)
+    }
+
+    fn merge_from_raw(&mut self, raw_plugin: RawPlugin, diagnostics: &mut Diagnostics) -> Result<(), ScanningError> {
         if let Some(desc) = raw_plugin.desc {
             self.desc = Some(desc);
         }
@@ -1124,57 +1491,153 @@ impl Plugin {
         }
 
         self.binds = raw_plugin.binds;
+        self.menu_binds = raw_plugin.menu_binds;
+        self.overrides |= raw_plugin.overrides;
+        if raw_plugin.keymap.is_some() {
+            self.keymap = raw_plugin.keymap;
+        }
 
         if let Some(fn_template) = raw_plugin.fn_template {
-            self.fn_template = fn_template.into();
+            match template::check(&fn_template, template::FN_VARS) {
+                Ok(()) => self.fn_template = fn_template.into(),
+                Err(e) => diagnostics.error(&Location::plugin(&self.name), format!("Invalid fn_template: {:#}", e)),
+            }
+        }
+
+        if raw_plugin.include_namespace.is_some() {
+            self.include_namespace = raw_plugin.include_namespace;
         }
 
+        if raw_plugin.sort.is_some() {
+            self.sort = raw_plugin.sort;
+        }
+        self.accept.merge(raw_plugin.accept);
+
         // Merge fns
+        let location = Location::plugin(&self.name);
         for mut raw_fn in raw_plugin.fns {
+            raw_fn.location = location.clone();
             if let Some(existing_fn) = self.fns.get_mut(&raw_fn.name) {
-                existing_fn.merge_from(raw_fn);
-                existing_fn.check()?;
+                existing_fn.merge_from(raw_fn, diagnostics);
+                let result = existing_fn.check(diagnostics);
+                diagnostics.check(&location, result)?;
             } else {
-                raw_fn.check()?;
-                self.fns.insert(raw_fn.name.clone(), raw_fn.into());
+                raw_fn.record_scanned(Layer::Config, &FnFlags::new());
+                let result = raw_fn.check(diagnostics);
+                if diagnostics.check(&location, result)?.is_some() {
+                    self.fns.insert(raw_fn.name.clone(), raw_fn);
+                }
             }
         }
         Ok(())
     }
-    fn map_includes(&mut self, plugins: HashMap<String, Plugin>) -> Result<(), ScanningError> {
+    // Flattens the plugins referenced by PGI actions into this one. Their own includes must already be resolved, see resolve_includes.
+    fn map_includes(&mut self, plugins: &HashMap<String, Plugin>, namespace: IncludeNamespace, diagnostics: &mut Diagnostics) {
         let mut includes=Vec::new();
         self.fns.retain(|_, fun| {
             if fun.flags.contains(&FnFlag::PGI) {
-                includes.push(fun.name.clone());
+                includes.push((fun.name.clone(), fun.location.clone()));
                 false
             } else {
                 true
             }
         });
-        for pg_key in includes {
+        includes.sort_by(|a, b| a.0.cmp(&b.0));
+        for (pg_key, location) in includes {
             if let Some(pg) = plugins.get(&pg_key) {
-                for (name,fun) in &pg.fns {
-                    if self.fns.contains_key(name) {
-                        return Err(ScanningError::DuplicateFunctionName(
-                            name.to_string(),
+                for fun in pg.sorted_fns() {
+                    if self.fns.contains_key(&fun.name) {
+                        diagnostics.error(&location, ScanningError::DuplicateFunctionName(
+                            fun.name.to_string(),
                             format!("Attempted to include {} into {}",pg_key,self.name)
-                        ));
+                        ).to_string());
+                        continue;
+                    }
+                    let mut fun = fun.clone();
+                    fun.provenance.record("plugin", Op::Set, &self.name, Layer::Include, &location);
+                    if fun.included_from.is_none() {
+                        fun.included_from = Some(pg.name.clone());
                     }
-                    self.fns.insert(name.clone(), fun.clone());
+                    if namespace == IncludeNamespace::Keep && fun.namespace.is_none() {
+                        if pg.name == "base" {
+                            fun.flags.insert(FnFlag::NN);
+                            fun.provenance.record("flags", Op::Add, "NN", Layer::Include, &location);
+                        }
+                        fun.namespace = Some(pg.get_alias());
+                    }
+                    self.fns.insert(fun.name.clone(), fun);
                 }
             } else {
-                warn!("Plugin {} not found in scanned plugins.", pg_key);
+                diagnostics.error(&location, ScanningError::MissingPlugin(self.name.to_string(), pg_key).to_string());
             }
         };
-        Ok(())
     }
 }
 
-fn plugin_from_dir(
-    path: &Path,
-    plugin_regex: &Regex,
-    linkedbin_regex: &Regex,
-) -> Result<Scanned, ScanningError> {
+// Resolves PGI includes depth-first so that included plugins are flattened before the plugins including them.
+// A PGI closing a cycle is reported and dropped, so the rest of the chain still resolves.
+fn resolve_includes(
+    plugins: &mut HashMap<String, Plugin>,
+    global_config: &GlobalConfig,
+    diagnostics: &mut Diagnostics,
+) {
+    let mut names: Vec<String> = plugins.keys().cloned().collect();
+    names.sort();
+    let mut resolved = HashSet::new();
+    for name in names {
+        resolve_plugin_includes(&name, plugins, &mut Vec::new(), &mut resolved, global_config, diagnostics);
+    }
+}
+
+fn resolve_plugin_includes(
+    name: &str,
+    plugins: &mut HashMap<String, Plugin>,
+    stack: &mut Vec<String>,
+    resolved: &mut HashSet<String>,
+    global_config: &GlobalConfig,
+    diagnostics: &mut Diagnostics,
+) {
+    if resolved.contains(name) {
+        return;
+    }
+    let Some(pg) = plugins.get(name) else {
+        return; // reported by map_includes of the including plugin
+    };
+    let mut includes: Vec<String> = pg
+        .fns
+        .values()
+        .filter(|fun| fun.flags.contains(&FnFlag::PGI))
+        .map(|fun| fun.name.clone())
+        .collect();
+    includes.sort();
+
+    stack.push(name.to_string());
+    for include in &includes {
+        if let Some(pos) = stack.iter().position(|n| n == include) {
+            let mut cycle = stack[pos..].to_vec();
+            cycle.push(include.to_string());
+            let fun = plugins.get_mut(name).unwrap().fns.remove(include).unwrap();
+            diagnostics.error(&fun.location, ScanningError::IncludeCycle(cycle.join(" -> ")).to_string());
+            continue;
+        }
+        resolve_plugin_includes(include, plugins, stack, resolved, global_config, diagnostics);
+    }
+    stack.pop();
+
+    if !includes.is_empty() {
+        let mut pg = plugins.remove(name).unwrap();
+        let namespace = pg.include_namespace.unwrap_or(global_config.include_namespace);
+        pg.map_includes(plugins, namespace, diagnostics);
+        plugins.insert(name.to_string(), pg);
+    }
+    resolved.insert(name.to_string());
+}
+
+fn plugin_from_dir(
+    path: &Path,
+    plugin_regex: &Regex,
+    linkedbin_regex: &Regex,
+) -> Result<Scanned, ScanningError> {
     let dir_name = path
         .file_name()
         .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid directory name"))?
@@ -1203,7 +1666,7 @@ fn plugin_from_dir(
             desc,
         };
 
-        Ok(Scanned::ScannedPlugin(plugin))
+        Ok(Scanned::Plugin(plugin))
     } else if let Some(caps) = linkedbin_regex.captures(&dir_name) {
         let name = caps
             .get(1)
@@ -1217,20 +1680,44 @@ fn plugin_from_dir(
             alias,
             desc,
         };
-        Ok(Scanned::ScannedLinkedbin(initial_plugin))
+        Ok(Scanned::Linkedbin(initial_plugin))
     } else {
         Ok(Scanned::None)
     }
 }
 
+fn insert_plugin(
+    plugins: &mut HashMap<String, InitialPlugin>,
+    plugin: InitialPlugin,
+    diagnostics: &mut Diagnostics,
+) {
+    if let Some(existing) = plugins.get(&plugin.name) {
+        diagnostics.error(
+            &Location::file(&plugin.name, &plugin.path),
+            format!(
+                "{} (also found at {})",
+                ScanningError::DuplicatePluginIdentifier(plugin.name.clone()),
+                existing.path.display()
+            ),
+        );
+    } else {
+        plugins.insert(plugin.name.clone(), plugin);
+    }
+}
+
 // used a generic function pointer for learning purpose
 fn scan_for_plugins(
     root_dir: &Path,
     plugin_regex: &Regex,
     linkedbin_regex: &Regex,
+    diagnostics: &mut Diagnostics,
 ) -> Result<(HashMap<String, InitialPlugin>, Vec<InitialPlugin>), ScanningError> {
     let mut plugins = HashMap::new();
     let mut linkedbins = Vec::new();
+    // created by the first build
+    if !root_dir.exists() {
+        return Ok((plugins, linkedbins));
+    }
 
     // Recursively scan directories
     for entry in fs::read_dir(root_dir)? {
@@ -1240,23 +1727,16 @@ fn scan_for_plugins(
         if path.is_dir() {
             // Scan the directory
             match plugin_from_dir(&path, plugin_regex, linkedbin_regex)? {
-                Scanned::ScannedPlugin(plugin) => {
-                    if plugins.contains_key(&plugin.name) {
-                        error!("Plugins hashmap: {:#?}", plugins);
-                        return Err(ScanningError::DuplicatePluginIdentifier(
-                            plugin.name.clone(),
-                        ));
-                    } else {
-                        plugins.insert(plugin.name.clone(), plugin);
-                    }
-                }
-                Scanned::ScannedLinkedbin(linkedbin) => linkedbins.push(linkedbin),
+                Scanned::Plugin(plugin) => insert_plugin(&mut plugins, plugin, diagnostics),
+                Scanned::Linkedbin(linkedbin) => linkedbins.push(linkedbin),
                 Scanned::None => (),
             };
 
             let (sub_plugins, sub_linkedbins) =
-                scan_for_plugins(&path, plugin_regex, linkedbin_regex)?;
-            plugins.extend(sub_plugins);
+                scan_for_plugins(&path, plugin_regex, linkedbin_regex, diagnostics)?;
+            for (_, plugin) in sub_plugins {
+                insert_plugin(&mut plugins, plugin, diagnostics);
+            }
             linkedbins.extend(sub_linkedbins);
         }
     }
@@ -1264,22 +1744,29 @@ fn scan_for_plugins(
     Ok((plugins, linkedbins))
 }
 
+fn duplicate_fn(existing: &Fun, global_config: &GlobalConfig) -> ScanningError {
+    ScanningError::DuplicateFunctionName(
+        existing.name.clone(),
+        format!("first defined at {}", existing.location.display(global_config)),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
 fn process_cmd(
     name: String,
-    path: Option<&PathBuf>,
+    location: &Location,
     alias: Option<String>,
     desc: Option<String>,
     cmd: String,
     flags: FnFlags,
     binds: Keybinds,
+    key: Option<String>,
+    complete: Option<String>,
     fns: &mut HashMap<String, Fun>,
     global_config: &GlobalConfig,
 ) -> Result<(), ScanningError> {
-    if fns.contains_key(&name) {
-        return Err(ScanningError::DuplicateFunctionName(
-            name,
-            path.map_or(cmd, |p| pathbuf_to_string(p, global_config)),
-        ));
+    if let Some(existing) = fns.get(&name) {
+        return Err(duplicate_fn(existing, global_config));
     }
     let fun = Fun {
         name: name.clone(),
@@ -1290,21 +1777,34 @@ fn process_cmd(
         cmd: Some(cmd),
         desc,
         fstring: None,
+        included_from: None,
+        namespace: None,
+        key,
+        menu_key: None,
+        overrides: false,
+        complete,
+        provenance: Provenance::default(),
+        location: location.clone(),
     };
     fns.insert(name, fun);
 
     Ok(())
 }
 
+// returns whether fstring matched fn_regex
+#[allow(clippy::too_many_arguments)]
 fn process_fstring(
     fstring: &str,
     path: Option<&PathBuf>,
+    location: &Location,
     flags: FnFlags,
     binds: Vec<String>,
+    key: Option<String>,
+    complete: Option<String>,
     fns: &mut HashMap<String, Fun>,
     global_config: &GlobalConfig,
     store_fstring: bool,
-) -> Result<(), ScanningError> {
+) -> Result<bool, ScanningError> {
     if let Some(caps) = global_config.fn_regex.captures(fstring) {
         let name = caps.get(1).map(|m| m.as_str().to_string()).ok_or_else(|| {
             io::Error::new(
@@ -1337,14 +1837,8 @@ fn process_fstring(
             None
         };
 
-        if fns.contains_key(&name) {
-            return Err(ScanningError::DuplicateFunctionName(
-                name,
-                path.map_or(
-                    fstring.unwrap_or("Neither fstring nor path available".to_string()),
-                    |p| p.to_string_lossy().into_owned(),
-                ),
-            ));
+        if let Some(existing) = fns.get(&name) {
+            return Err(duplicate_fn(existing, global_config));
         }
         let fun = Fun {
             name: name.clone(),
@@ -1355,17 +1849,20 @@ fn process_fstring(
             cmd: None,
             binds,
             fstring: fstring.clone(),
+            included_from: None,
+            namespace: None,
+            key,
+            menu_key: None,
+            overrides: false,
+            complete,
+            provenance: Provenance::default(),
+            location: location.clone(),
         };
         fns.insert(name.clone(), fun);
+        Ok(true)
     } else {
-        if !path.is_none() {
-            warn!(
-                "File name does not match regex: {}",
-                path.unwrap().display()
-            )
-        };
+        Ok(false)
     }
-    Ok(())
 }
 
 fn recurse_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
@@ -1382,109 +1879,232 @@ fn recurse_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
     Ok(())
 }
 
-fn populate_plugins<'a>(
+// The files of a plugin directory that get parsed. The generated file lives next to them but is never parsed.
+fn plugin_files(ip: &InitialPlugin, global_config: &GlobalConfig) -> io::Result<Vec<PathBuf>> {
+    // special base plugin is fully recursive
+    let mut files = if ip.name == "base" {
+        let mut files = Vec::new();
+        recurse_files(&ip.path, &mut files)?;
+        files
+    } else {
+        fs::read_dir(&ip.path)?
+            .filter_map(|entry| entry.ok())
+            .map(|e| e.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>()
+    };
+    let generated_file = ip.path.join(&global_config.generated_file);
+    let generated_zwc = zwc_path(&generated_file);
+    files.retain(|path| path != &generated_file && path != &generated_zwc);
+    files.sort();
+    Ok(files)
+}
+
+// Directories whose files are unchanged since the cached scan are not parsed again, see ScanCache
+fn populate_plugins(
     plugins: &mut HashMap<String, Plugin>,
     to_parse: impl Iterator<Item = InitialPlugin>,
     default_flags: &FnFlags,
     provide_envs: &mut HashMap<String, (String, i32)>, // fstring -> plugin -> function
-    global_config: &'a GlobalConfig,
+    global_config: &GlobalConfig,
+    diagnostics: &mut Diagnostics,
+    cache: &mut ScanCache,
 ) -> Result<(), ScanningError> {
     for ip in to_parse {
-        let ip_clone = ip.clone();
-        let pg = plugins
-            .entry(ip_clone.name.clone())
-            .or_insert(ip.to_plugin(HashMap::new()));
-        let fns = &mut pg.fns;
-
-        // special base plugin is fully recursive
-        let files = if pg.name == "base" {
-            let mut files = Vec::new();
-            recurse_files(&ip_clone.path, &mut files)?;
-            files
-        } else {
-            fs::read_dir(&ip_clone.path)?
-                .filter_map(|entry| entry.ok())
-                .map(|e| e.path())
-                .collect::<Vec<_>>()
+        let files = plugin_files(&ip, global_config)?;
+        let stamps = files
+            .iter()
+            .map(|path| Stamp::of(path))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let scanned = match cache.take(&ip.path, &stamps) {
+            Some(cached) => {
+                debug!("Reusing the cached scan of {}", ip.path.display());
+                diagnostics.extend(cached.diagnostics.clone());
+                cached
+            }
+            None => {
+                let mark = diagnostics.len();
+                let mut fns = HashMap::new();
+                let (sources, bash_sources) =
+                    populate_dir(&ip, &files, &mut fns, default_flags, provide_envs, global_config, diagnostics)?;
+                let mut fns: Vec<CachedFun> = fns
+                    .into_values()
+                    .map(|fun| CachedFun { location: fun.location.clone(), provenance: fun.provenance.clone(), fun })
+                    .collect();
+                fns.sort_by(|a, b| a.fun.name.cmp(&b.fun.name));
+                CachedDir { stamps, fns, sources, bash_sources, diagnostics: diagnostics.since(mark) }
+            }
         };
 
-        for path in files {
-            if path.is_file() && path.is_executable() {
-                let fname = path
-                    .file_name()
-                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid file name"))?
-                    .to_string_lossy();
-
-                // process flags
-                let (fstring, ext): (&str, Option<&str>) = match fname.split_once('.') {
-                    Some((fstring, ext)) => (fstring, Some(ext)),
-                    None => (&fname, None),
-                };
-
-                let (e_name, e_alias, e_desc, mut e_flags, e_binds, _) = process_ext(ext);
-                e_flags.extend(default_flags.iter().cloned());
-                // this is useless but we have it for compatibility?
-                if e_flags.contains(&FnFlag::CMD) {
-                    let name = e_name.unwrap_or(match global_config.name_from_cmd_regex.captures(fstring) {
-                        Some(caps) => caps
-                            .get(1)
-                            .map(|m| m.as_str().to_string())
-                            .unwrap_or(fstring.to_string()),
-                        None => fstring.to_string(),
-                    });
-                    process_cmd(
-                        name,
-                        Some(&path),
-                        e_alias,
-                        e_desc,
-                        fstring.to_string(),
-                        e_flags,
-                        e_binds,
-                        fns,
-                        &global_config,
-                    )?;
-                } else {
-                    process_fstring(
-                        &fstring,
-                        Some(&path),
-                        e_flags,
-                        e_binds,
-                        fns,
-                        &global_config,
-                        false,
-                    )?;
-                }
+        let pg = plugins
+            .entry(ip.name.clone())
+            .or_insert(ip.clone().into_plugin(HashMap::new()));
+        for cached in &scanned.fns {
+            if let Some(existing) = pg.fns.get(&cached.fun.name) {
+                diagnostics.error(&cached.location, duplicate_fn(existing, global_config).to_string());
+                continue;
+            }
+            let mut fun = cached.fun.clone();
+            fun.location = cached.location.clone();
+            fun.provenance = cached.provenance.clone();
+            pg.fns.insert(fun.name.clone(), fun);
+        }
+        pg.sources.extend(scanned.sources.iter().cloned());
+        pg.bash_sources.extend(scanned.bash_sources.iter().cloned());
+        cache.insert(&ip.path, scanned);
+    }
+    Ok(())
+}
+
+// The kind of source a file is by its extension, and its name without it
+fn source_kind<'a>(file_name: &'a str, global_config: &GlobalConfig) -> Option<(&'a str, bool)> {
+    let (stem, extension) = file_name.rsplit_once('.')?;
+    if global_config.source_extensions.iter().any(|e| e == extension) {
+        Some((stem, false))
+    } else if global_config.bash_source_extensions.iter().any(|e| e == extension) {
+        Some((stem, true))
+    } else {
+        None
+    }
+}
+
+// Parses the files of one plugin directory into fns, returns the sources to add to the plugin and its bash sources
+fn populate_dir(
+    ip: &InitialPlugin,
+    files: &[PathBuf],
+    fns: &mut HashMap<String, Fun>,
+    default_flags: &FnFlags,
+    provide_envs: &mut HashMap<String, (String, i32)>,
+    global_config: &GlobalConfig,
+    diagnostics: &mut Diagnostics,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>), ScanningError> {
+    let mut sources = Vec::new();
+    let mut bash_sources = Vec::new();
+    for path in files.iter().cloned() {
+        let location = Location::file(&ip.name, &path);
+        let before: HashSet<String> = fns.keys().cloned().collect();
+        if path.is_file() && path.is_executable() {
+            let fname = path
+                .file_name()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid file name"))?
+                .to_string_lossy();
+
+            // process flags
+            let (fstring, ext): (&str, Option<&str>) = match fname.split_once('.') {
+                Some((fstring, ext)) => (fstring, Some(ext)),
+                None => (&fname, None),
+            };
+
+            let (header, header_location) = read_header(&path, &location, diagnostics);
+            let from_file_name = process_ext(ext, fstring.chars().count() + 2, diagnostics, &location);
+            let (e_name, e_alias, e_desc, mut e_flags, e_binds, _, e_key, e_complete) =
+                merge_ext(header.clone(), from_file_name.clone());
+            let defaults: FnFlags = default_flags.difference(&e_flags).cloned().collect();
+            e_flags.extend(default_flags.iter().cloned());
+            // this is useless but we have it for compatibility?
+            if e_flags.contains(&FnFlag::CMD) {
+                let name = e_name.unwrap_or(match global_config.name_from_cmd_regex.captures(fstring) {
+                    Some(caps) => caps
+                        .get(1)
+                        .map(|m| m.as_str().to_string())
+                        .unwrap_or(fstring.to_string()),
+                    None => fstring.to_string(),
+                });
+                let result = process_cmd(
+                    name,
+                    &location,
+                    e_alias,
+                    e_desc,
+                    fstring.to_string(),
+                    e_flags,
+                    e_binds,
+                    e_key,
+                    e_complete,
+                    fns,
+                    global_config,
+                );
+                diagnostics.check(&location, result)?;
             } else {
-                if let Some(basename) = path.file_name() {
-                    if let Some(basename_str) = basename.to_str() {
-                        if let Some(basename_str) = basename_str.strip_suffix(".zshrc") {
-                            let mut file_flags = FnFlags::new();
-                            if let Some(pos) = basename_str.rfind('.') {
-                                parse_file_flags(&basename_str[pos + 1..], &mut file_flags)
-                            }
-                            debug!("Populating from {}", &path.display());
-                            match populate_from_file(
-                                &path,
-                                fns,
-                                provide_envs,
-                                &ip_clone,
-                                file_flags,
-                                global_config,
-                            ) {
-                                Ok(_) => pg.sources.push(path),
-                                Err(err) => error!("Failed to parse {}: {}", path.display(), err),
-                            }
+                let result = process_fstring(
+                    fstring,
+                    Some(&path),
+                    &location,
+                    e_flags,
+                    e_binds,
+                    e_key,
+                    e_complete,
+                    fns,
+                    global_config,
+                    false,
+                );
+                match diagnostics.check(&location, result)? {
+                    Some(true) => {
+                        // what fn_regex captured from the file name comes first
+                        for fun in scanned(fns, &before) {
+                            fun.alias = fun.alias.take().or(e_alias.clone());
+                            fun.desc = fun.desc.take().or(e_desc.clone());
                         }
                     }
+                    Some(false) => diagnostics.warn(&location, "File name does not match fn_regex, skipping"),
+                    None => {}
                 }
             }
+            for fun in scanned(fns, &before) {
+                fun.record_scanned(Layer::FileName, &defaults);
+                for field in header_fields(&header, &from_file_name, fun) {
+                    fun.provenance.reattribute(field, Layer::FileName, Layer::Header, &header_location);
+                }
+            }
+        } else {
+            let kind = path.file_name().and_then(|name| name.to_str()).and_then(|name| source_kind(name, global_config));
+            let Some((basename_str, bash)) = kind else {
+                continue;
+            };
+            // only defined when bash sources them
+            if bash && global_config.shell != Shell::Bash {
+                debug!("Not parsing the bash source {}", &path.display());
+                bash_sources.push(path);
+                continue;
+            }
+            let mut file_flags = FnFlags::new();
+            if let Some(pos) = basename_str.rfind('.') {
+                parse_file_flags(&basename_str[pos + 1..], &mut file_flags, diagnostics, &location)
+            }
+            debug!("Populating from {}", &path.display());
+            match populate_from_file(
+                &path,
+                fns,
+                provide_envs,
+                ip,
+                file_flags.clone(),
+                global_config,
+                diagnostics,
+            ) {
+                Ok(_) => {
+                    for fun in scanned(fns, &before) {
+                        fun.record_scanned(Layer::Directive, &file_flags);
+                    }
+                    if bash {
+                        bash_sources.push(path)
+                    } else {
+                        sources.push(path)
+                    }
+                }
+                Err(err) => diagnostics.error(&location, format!("Failed to parse: {}", err)),
+            }
         }
     }
 
-    Ok(())
+    Ok((sources, bash_sources))
 }
 
-fn parse_file_flags(val: &str, flags: &mut FnFlags) {
+// The actions just scanned from one file, those whose name was not in fns before it
+fn scanned<'a>(fns: &'a mut HashMap<String, Fun>, before: &'a HashSet<String>) -> impl Iterator<Item = &'a mut Fun> {
+    fns.iter_mut().filter(move |(key, _)| !before.contains(*key)).map(|(_, fun)| fun)
+}
+
+fn parse_file_flags(val: &str, flags: &mut FnFlags, diagnostics: &mut Diagnostics, location: &Location) {
     for flag in val.split(',') {
         match FnFlag::from_str(flag) {
             Ok(parsed_flag) => {
@@ -1492,69 +2112,143 @@ fn parse_file_flags(val: &str, flags: &mut FnFlags) {
             }
             Err(_) => {
                 if !flags.is_empty() {
-                    warn!("Encountered an invalid flag {} in {}", flag, val);
+                    diagnostics.warn(location, format!("Encountered an invalid flag {} in {}", flag, val));
                 }
             }
         }
     }
 }
 
-fn process_ext(ext: Option<&str>) -> (Option<String>, Option<String>, Option<String>, FnFlags, Keybinds, Option<String>) {
+// The modifiers of the fzs: header of an executable, with the location of its first line
+fn read_header(path: &Path, location: &Location, diagnostics: &mut Diagnostics) -> (Ext, Location) {
+    let lines = match header::read(path) {
+        Ok(lines) => lines,
+        Err(err) => {
+            diagnostics.warn(location, format!("Failed to read the header: {}", err));
+            Vec::new()
+        }
+    };
+    let header_location = lines.first().map_or(location.clone(), |(line, _, _)| location.at_line(*line));
+    let ext = lines.into_iter().fold(Ext::default(), |ext, (line, column, modifiers)| {
+        merge_ext(ext, process_ext(Some(&modifiers), column, diagnostics, &location.at_line(line)))
+    });
+    (ext, header_location)
+}
+
+// Modifiers in upper replace those in lower, flags and binds as a whole
+fn merge_ext(lower: Ext, upper: Ext) -> Ext {
+    let (name, alias, desc, flags, binds, cmd, key, complete) = upper;
+    (
+        name.or(lower.0),
+        alias.or(lower.1),
+        desc.or(lower.2),
+        if flags.is_empty() { lower.3 } else { flags },
+        if binds.is_empty() { lower.4 } else { binds },
+        cmd.or(lower.5),
+        key.or(lower.6),
+        complete.or(lower.7),
+    )
+}
+
+// The fields of fun the header set, which neither the modifiers of the file name nor fn_regex overrode
+fn header_fields(header: &Ext, file_name: &Ext, fun: &Fun) -> Vec<&'static str> {
+    [
+        ("alias", header.1.is_some() && file_name.1.is_none() && header.1 == fun.alias),
+        ("desc", header.2.is_some() && file_name.2.is_none() && header.2 == fun.desc),
+        ("flags", !header.3.is_empty() && file_name.3.is_empty()),
+        ("binds", !header.4.is_empty() && file_name.4.is_empty()),
+        ("key", header.6.is_some() && file_name.6.is_none()),
+        ("complete", header.7.is_some() && file_name.7.is_none()),
+    ]
+    .into_iter()
+    .filter_map(|(field, set)| set.then_some(field))
+    .collect()
+}
+
+// name, alias, desc, flags, binds, cmd and key of a file extension or a # : line
+type Ext = (Option<String>, Option<String>, Option<String>, FnFlags, Keybinds, Option<String>, Option<String>, Option<String>);
+
+// column is where ext starts in the line, or in the file name, so that diagnostics point at the bad modifier
+fn process_ext(ext: Option<&str>, column: usize, diagnostics: &mut Diagnostics, location: &Location) -> Ext {
     let mut name = None;
     let mut alias = None;
     let mut flags = FnFlags::new();
-    let mut binds = Keybinds::new(); // assuming binds is a Vec or similar structure
+    let mut flags_seen = false;
+    let mut binds = Keybinds::new();
     let mut capturing_desc = false;
     let mut desc_parts = Vec::new();
     let mut cmd = None;
+    let mut key = None;
+    let mut complete = None;
 
     if let Some(ext_string) = ext {
-        for word in ext_string.split_whitespace() {
-            if capturing_desc {
-                desc_parts.push(word.to_string());
+        let tokens = match modifier::tokenize(ext_string) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                diagnostics.error(&location.at_column(column + err.column - 1), err.message);
+                Vec::new()
+            }
+        };
+        for token in tokens {
+            let token_location = location.at_column(column + token.column - 1);
+            let val = token.value;
+            // an unquoted desc still takes the words after it, up to the next modifier
+            if capturing_desc && token.key.is_none() && !token.quoted {
+                desc_parts.push(val);
                 continue;
             }
-            if let Some(val) = word.strip_prefix("name=") {
-                name = Some(val.to_string());
-            } else if let Some(val) = word.strip_prefix("alias=") {
-                alias = Some(val.to_string());
-            } else if let Some(val) = word.strip_prefix("desc=") {
-                capturing_desc = true;
-                desc_parts.push(val.to_string());
-            } else if let Some(val) = word.strip_prefix("binds=") {
-                for bind in val.split(',') {
-                    binds.push(bind.to_string());
+            capturing_desc = false;
+            match token.key.as_deref() {
+                Some("name") => name = Some(val),
+                Some("alias") => alias = Some(val),
+                Some("key") => key = Some(val),
+                Some("complete") => complete = Some(val),
+                Some("desc") => {
+                    capturing_desc = !token.quoted;
+                    desc_parts = vec![val];
+                }
+                Some("binds") => {
+                    for bind in val.split(',') {
+                        binds.push(bind.to_string());
+                    }
                 }
-            } else if let Some(val) = word.strip_prefix("cmd=") {
-                flags.insert(FnFlag::CMD);
+                Some("cmd") => {
+                    flags.insert(FnFlag::CMD);
                     name = name.or_else(|| {
                         Some(
-                            val.chars()
-                                .filter(|c| c.is_alphanumeric() || *c == '_')
-                                .collect::<String>(),
+                            val.split_whitespace()
+                                .map(|word| word.chars().filter(|c| c.is_alphanumeric() || *c == '_').collect::<String>())
+                                .collect::<Vec<String>>()
+                                .join("_"),
                         )
                     });
-                cmd = Some(val.to_string());
-            } else if let Some(_) = word.strip_prefix(".") {
-                () // ignore extensions like .zsh
-            } else {
-                let val = word.strip_prefix("flags=").unwrap_or(word);
-                if !flags.is_empty() {
-                    warn!("Flags (unprefixed words) defined twice in {}, skipping.", ext_string);
-                } else {
-                    for flag in val.split(',') {
-                        match FnFlag::from_str(flag) { // potentially we want to filter valid?
-                            Ok(parsed_flag) => {
-                                flags.insert(parsed_flag);
-                            },
-                            Err(_) => {
-                                if !flags.is_empty() {
-                                    warn!("Encountered an invalid flag in {}", ext_string);
+                    cmd = Some(val);
+                }
+                Some("flags") | None => {
+                    if token.key.is_none() && val.starts_with('.') {
+                        // ignore extensions like .zsh
+                    } else if flags_seen {
+                        diagnostics.warn(&token_location, format!("Flags defined twice in {}, skipping.", ext_string));
+                    } else {
+                        flags_seen = true;
+                        for flag in val.split(',') {
+                            match FnFlag::from_str(flag) { // potentially we want to filter valid?
+                                Ok(parsed_flag) => {
+                                    flags.insert(parsed_flag);
+                                },
+                                Err(_) => {
+                                    if !flags.is_empty() {
+                                        diagnostics.warn(&token_location, format!("Encountered an invalid flag {} in {}", flag, ext_string));
+                                    }
                                 }
                             }
                         }
                     }
                 }
+                Some(unknown) => diagnostics.error(
+                    &token_location,
+                    format!("Unknown modifier {}=, expected one of {}", unknown, modifier::KEYS.join(", ")),
+                ),
             }
         }
     }
@@ -1564,7 +2258,7 @@ fn process_ext(ext: Option<&str>) -> (Option<String>, Option<String>, Option<Str
         None
     };
 
-    (name, alias, desc, flags, binds, cmd)
+    (name, alias, desc, flags, binds, cmd, key, complete)
 }
 
 // todo: use regex for safer substitution
@@ -1579,12 +2273,14 @@ fn replace_shell<T: Initial>(cmd_string: &str, pg: &T) -> String {
 fn populate_from_file<T: Initial>(
     file_path: &PathBuf,
     fns: &mut HashMap<String, Fun>,
-    provide_envs: &mut HashMap<String, (String, i32)>,
+    _provide_envs: &mut HashMap<String, (String, i32)>,
     pg: &T,
     file_flags: FnFlags,
     global_config: &GlobalConfig,
+    diagnostics: &mut Diagnostics,
 ) -> Result<(), ScanningError> {
     let file = File::open(file_path)?;
+    let file_location = Location::file(pg.get_name_ref(), file_path);
     let reader = BufReader::new(file);
 
     let mut parse_next = false;
@@ -1594,8 +2290,17 @@ fn populate_from_file<T: Initial>(
     let mut e_alias = None;
     let mut e_desc = None;
     let mut e_cmd: Option<String>;
+    let mut e_key = None;
+    let mut e_complete = None;
 
-    for line in reader.lines() {
+    // function name, function name(), name() and name () alike, with or without the $
+    let decl_pattern =
+        regex::Regex::new(r"(?:^|\s)(?:function\s+([\$a-zA-Z0-9_.\-\&]+)|([\$a-zA-Z0-9_.\-\&]+)\s*\(\s*\))").unwrap();
+    let var_pattern = regex::Regex::new(r"^\$([a-zA-Z0-9_]+)$").unwrap(); // https://stackoverflow.com/questions/2821043/allowed-characters-in-linux-environment-variable-names
+    let alias_pattern = regex::Regex::new(r"alias ([\$a-zA-Z0-9_.\-\&]+)=").unwrap();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_location = file_location.at_line(index + 1);
         let line = match line {
             Ok(l) => l,
             Err(e) => {
@@ -1605,34 +2310,39 @@ fn populate_from_file<T: Initial>(
         };
         let pattern = "# :";
 
-        let func_pattern = regex::Regex::new(r"(?:^| )\$([a-zA-Z0-9_]+)\s*\(").unwrap(); // https://stackoverflow.com/questions/2821043/allowed-characters-in-linux-environment-variable-names
-        let cmd_pattern = regex::Regex::new(r"(?:^| )([\$a-zA-Z0-9_.\-\&]+)\s*\(").unwrap();
-        let alias_pattern = regex::Regex::new(r"alias ([\$a-zA-Z0-9_.\-\&]+)=").unwrap();
-
-        if let Some(directives) = line.trim_start().strip_prefix(&pattern) {
-            (e_name, e_alias, e_desc, flags, binds, e_cmd) = process_ext(Some(directives));
-            if flags.contains(&FnFlag::PG) {
+        if let Some(directives) = line.trim_start().strip_prefix(pattern) {
+            let location = line_location;
+            let column = line[..line.len() - directives.len()].chars().count() + 1;
+            (e_name, e_alias, e_desc, flags, binds, e_cmd, e_key, e_complete) =
+                process_ext(Some(directives), column, diagnostics, &location);
+            if flags.contains(&FnFlag::PG) || flags.contains(&FnFlag::PGI) {
                 debug!("found plugin {}", &directives);
                 if let Some(name) = e_name {
-                    if fns.contains_key(&name) {
-                        return Err(ScanningError::DuplicateFunctionName(
-                            name,
-                            pathbuf_to_string(file_path, global_config)),
-                        );
+                    if let Some(existing) = fns.get(&name) {
+                        diagnostics.error(&location, duplicate_fn(existing, global_config).to_string());
+                    } else {
+                        let fun = Fun {
+                                    name: name.clone(),
+                                    alias: None,
+                                    desc: e_desc,
+                                    flags,
+                                    cmd: None,
+                                    binds,
+                                    bin: None,
+                                    fstring: None,
+                                    included_from: None,
+                                    namespace: None,
+                                    key: e_key.take(),
+                                    menu_key: None,
+                                    overrides: false,
+                                    complete: e_complete.take(),
+                                    provenance: Provenance::default(),
+                                    location: location.clone(),
+                                };
+                        fns.insert(name.clone(), fun);
                     }
-                    let fun = Fun {
-                                name: name.clone(),
-                                alias: None,
-                                desc: e_desc,
-                                flags,
-                                cmd: None,
-                                binds,
-                                bin: None,
-                                fstring: None
-                            };
-                    fns.insert(name.clone(), fun);
                 } else {
-                    warn!("Encountered PG declaration without a name in {}, skipping", pathbuf_to_string(file_path, global_config));
+                    diagnostics.warn(&location, "Encountered PG declaration without a name, skipping");
                 }
                 flags = FnFlags::new();
                 binds = Keybinds::new();
@@ -1641,17 +2351,20 @@ fn populate_from_file<T: Initial>(
                 e_desc=None;
             } else if let Some(cmd) = e_cmd {
                 debug!("found cmd {}", &directives);
-                process_cmd(
+                let result = process_cmd(
                             e_name.unwrap(),
-                            Some(file_path),
+                            &location,
                             e_alias,
                             e_desc,
                             cmd,
                             flags,
                             binds,
+                            e_key.take(),
+                            e_complete.take(),
                             fns,
                             global_config,
-                        )?;
+                        );
+                diagnostics.check(&location, result)?;
                 flags = FnFlags::new();
                 binds = Keybinds::new();
                 e_alias = None; // compiler needs help
@@ -1666,49 +2379,22 @@ fn populate_from_file<T: Initial>(
                 continue;
             };
             parse_next = false;
+            let location = line_location;
             flags.extend(file_flags.clone());
-            if flags.contains(&FnFlag::CMD) {
-                if let Some(caps) = cmd_pattern.captures(&line) {
+            let declared = decl_pattern
+                .captures(&line)
+                .and_then(|caps| caps.get(1).or(caps.get(2)))
+                .map(|m| m.as_str().to_string());
+            if flags.contains(&FnFlag::AL) && !flags.contains(&FnFlag::CMD) {
+                if let Some(caps) = alias_pattern.captures(&line) {
                     if let Some(cstring) = caps.get(1) {
-                        flags = flags.into_iter().filter(|flag| if flag.cannot_on_script() { warn!("Flag {:?} cannot decorate a function ({}), skipping", flag, file_path.display()); false } else {true}).collect();
-                        // todo: check namespace or command has no whitespace before allowing WG flag
-                        let cstring=replace_shell(cstring.into(), pg);
-                        debug!("found cstring {}", &cstring);
-                        let name = e_name.unwrap_or(match global_config.name_from_cmd_regex.captures(&cstring) {
-                            Some(caps) => caps
-                                .get(1)
-                                .map(|m| m.as_str().to_string())
-                                .unwrap_or(cstring.to_string()),
-                            None => cstring.to_string(),
-                        });
-                        process_cmd(
-                            name,
-                            Some(file_path),
-                            e_alias,
-                            e_desc,
-                            cstring.to_string(),
-                            flags,
-                            binds,
-                            fns,
-                            &global_config,
-                        )?;
-                    }
-                }
-                
-            }   else {
-                if flags.contains(&FnFlag::AL) {
-                    if let Some(caps) = alias_pattern.captures(&line) {
-                        if let Some(cstring) = caps.get(1) {
-                            let alias=replace_shell(cstring.into(), pg);
-                            debug!("found alias {}", &alias);
-                            let name= global_config.name_from_alias_template.replace("{{ alias }}", &alias.as_str());
-    
-                            if fns.contains_key(&name) {
-                                return Err(ScanningError::DuplicateFunctionName(
-                                    name,
-                                    line
-                                ));
-                            }
+                        let alias=replace_shell(cstring.into(), pg);
+                        debug!("found alias {}", &alias);
+                        let name = render_template(&global_config.name_from_alias_template, minijinja::context! { alias => alias.as_str() }, global_config);
+
+                        if let Some(existing) = fns.get(&name) {
+                            diagnostics.error(&location, duplicate_fn(existing, global_config).to_string());
+                        } else {
                             let fun = Fun {
                                 name: name.clone(),
                                 alias: alias.as_str().to_string().into(),
@@ -1717,33 +2403,81 @@ fn populate_from_file<T: Initial>(
                                 cmd: alias.as_str().to_string().into(),
                                 binds,
                                 bin: None,
-                                fstring: None
+                                fstring: None,
+                                included_from: None,
+                                namespace: None,
+                                key: e_key.take(),
+                                menu_key: None,
+                                overrides: false,
+                                complete: e_complete.take(),
+                                provenance: Provenance::default(),
+                                location: location.clone(),
                             };
                             fns.insert(name.clone(), fun);
                         }
                     }
-                }   else if let Some(caps) = func_pattern.captures(&line) {
-                    flags = flags.into_iter().filter(|flag| if flag.cannot_on_script() { warn!("Flag {:?} cannot decorate a function ({}), skipping", flag, file_path.display()); false } else {true}).collect();
-                    if let Some(cstring) = caps.get(1) {
-                        let fstring=replace_shell(cstring.into(), pg);
+                }
+            } else if let Some(declared) = declared {
+                flags.retain(|flag| if flag.cannot_on_script() { diagnostics.warn(&location, format!("Flag {} cannot decorate a function, skipping", flag)); false } else {true});
+                match var_pattern.captures(&declared).and_then(|caps| caps.get(1)) {
+                    Some(var) if !flags.contains(&FnFlag::CMD) => {
+                        let fstring=replace_shell(var.as_str(), pg);
                         debug!("found fstring {}", &fstring.as_str());
-                        process_fstring(
+                        let result = process_fstring(
                             fstring.as_str(),
                             None,
+                            &location,
                             flags,
                             binds,
+                            e_key.take(),
+                            e_complete.take(),
                             fns,
                             global_config,
                             true,
-                        )?;
+                        );
+                        match diagnostics.check(&location, result)? {
+                            Some(true) => {
+                                // what fn_regex captured from the function name comes first
+                                if let Some(fun) = fns.values_mut().find(|fun| fun.location == location) {
+                                    fun.alias = fun.alias.take().or(e_alias.take());
+                                    fun.desc = fun.desc.take().or(e_desc.take());
+                                }
+                            }
+                            Some(false) => diagnostics.warn(&location, format!("Function name {} does not match fn_regex, skipping", fstring)),
+                            None => {}
+                        }
+                    }
+                    _ => {
+                        // a function with a literal name is called by it, as if declared with CMD
+                        flags.insert(FnFlag::CMD);
+                        // todo: check namespace or command has no whitespace before allowing WG flag
+                        let cstring=replace_shell(&declared, pg);
+                        debug!("found cstring {}", &cstring);
+                        let name = e_name.unwrap_or(match global_config.name_from_cmd_regex.captures(&cstring) {
+                            Some(caps) => caps
+                                .get(1)
+                                .map(|m| m.as_str().to_string())
+                                .unwrap_or(cstring.to_string()),
+                            None => cstring.to_string(),
+                        });
+                        let result = process_cmd(
+                            name,
+                            &location,
+                            e_alias,
+                            e_desc,
+                            cstring.to_string(),
+                            flags,
+                            binds,
+                            e_key.take(),
+                            e_complete.take(),
+                            fns,
+                            global_config,
+                        );
+                        diagnostics.check(&location, result)?;
                     }
-                } else {
-                    warn!(
-                        "No match found following a declared line: {} in {}",
-                        line,
-                        file_path.display()
-                    );
                 }
+            } else {
+                diagnostics.warn(&location, format!("No match found following a declared line: {}", line));
             }
             flags = FnFlags::new();
             binds = Keybinds::new();
@@ -1775,104 +2509,180 @@ fn symlink_fns<'a>(
     }
 
     // Symlink all executables associated with the plugins to the `path_dir` directory, using the appropriate naming scheme.
+    for (name, source_path) in planned_symlinks(plugins, global_config) {
+        let symlink_path = global_config.path_dir.join(name);
+        if let Err(e) = {
+            debug!(
+                "Symlinking {} -> {}",
+                &source_path.display(),
+                &symlink_path.display()
+            );
+            symlink_file(&source_path, &symlink_path)
+        } {
+            restore_symlinks(temp_symlink_dir.path(), &global_config.path_dir)?; // todo: don't early exit
+            return Err(CreationError::SymlinkError(
+                source_path,
+                symlink_path,
+                e.to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// symlink name in path_dir -> executable
+fn current_symlinks(global_config: &GlobalConfig) -> Result<BTreeMap<String, PathBuf>, io::Error> {
+    let mut links = BTreeMap::new();
+    if !global_config.path_dir.exists() {
+        return Ok(links);
+    }
+    for entry in fs::read_dir(&global_config.path_dir)? {
+        let path = entry?.path();
+        if fs::symlink_metadata(&path)?.file_type().is_symlink() {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            links.insert(name, fs::read_link(&path)?);
+        }
+    }
+    Ok(links)
+}
+
+// Like symlink_fns, but only touches the symlinks that differ from the plan
+fn sync_symlinks<'a>(
+    plugins: impl Iterator<Item = &'a Plugin>,
+    global_config: &GlobalConfig,
+) -> Result<(), CreationError> {
+    let current = current_symlinks(global_config)?;
+    let planned = planned_symlinks(plugins, global_config);
+    for (name, target) in &current {
+        if planned.get(name) != Some(target) {
+            debug!("Removing symlink {}", name);
+            fs::remove_file(global_config.path_dir.join(name))?;
+        }
+    }
+    for (name, source_path) in planned {
+        if current.get(&name) != Some(&source_path) {
+            let symlink_path = global_config.path_dir.join(&name);
+            debug!(
+                "Symlinking {} -> {}",
+                &source_path.display(),
+                &symlink_path.display()
+            );
+            if let Err(e) = symlink_file(&source_path, &symlink_path) {
+                return Err(CreationError::SymlinkError(
+                    source_path,
+                    symlink_path,
+                    e.to_string(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn planned_symlinks<'a>(
+    plugins: impl Iterator<Item = &'a Plugin>,
+    global_config: &GlobalConfig,
+) -> BTreeMap<String, PathBuf> {
+    let mut links = BTreeMap::new();
     for pg in plugins {
         for fun in pg.fns.values() {
             if !fun.flags.contains(&FnFlag::WG) && !fun.flags.contains(&FnFlag::PG) {
                 if let Some(source_path) = &fun.bin {
-                    let symlink_path = global_config.path_dir.join(fun.get_cmd(pg, global_config));
-                    if let Err(e) = {
-                        debug!(
-                            "Symlinking {} -> {}",
-                            &source_path.display(),
-                            &symlink_path.display()
-                        );
-                        symlink_file(&source_path, &symlink_path)
-                    } {
-                        restore_symlinks(temp_symlink_dir.path(), &global_config.path_dir)?; // todo: don't early exit
-                        return Err(CreationError::SymlinkError(
-                            source_path.clone(),
-                            symlink_path.clone(),
-                            e.to_string(),
-                        ));
-                    }
+                    links.insert(fun.get_cmd(pg, global_config), source_path.clone());
                 }
             }
         }
     }
-
-    Ok(())
+    links
 }
 
-fn templatize_contents<'a>(
-    mut contents: String,
-    global_config: &'a GlobalConfig,
-    replacements: &HashMap<&str, Cow<'a, str>>
+// Renders template_file or an init template, see template::FILE_VARS
+fn templatize_contents(
+    source: &str,
+    global_config: &GlobalConfig,
+    mut ctx: BTreeMap<&str, minijinja::Value>
 ) -> Result<String, std::io::Error> {
-    for (key, replacement) in replacements {
-        contents = contents.replace(&format!("{{{{ {} }}}}", key), replacement);
-    }
-    contents = contents.replace(
-        &format!("{{{{ {} }}}}", "fzs_name"),
-        &global_config.fzs_name,
-    ).replace(
-        &format!("{{{{ {} }}}}", "fzs_root_dir"),
-        &pathbuf_to_string_basic(&global_config.root_dir),
-    ).replace(
-        &format!("{{{{ {} }}}}", "fzs_path_dir"),
-        &pathbuf_to_string_basic(&global_config.path_dir),
-    ).replace(
-        &format!("{{{{ {} }}}}", "fzs_data_dir"),
-        &pathbuf_to_string_basic(&global_config.data_dir),
-    ).replace(
-        &format!("{{{{ {} }}}}", "fzs_config_dir"),
-        &pathbuf_to_string_basic(&global_config.config_dir),
-    ).replace(
-        &format!("{{{{ {} }}}}", "fzs_provides_file"),
-        &pathbuf_to_string(&global_config.provides_file, &global_config),
-    ).replace(
-        &format!("{{{{ {} }}}}", "fzs_fzf_dir_cmd"),
-        &global_config.fzs_fzf_dir_cmd,
-    ).replace(
-        &format!("{{{{ {} }}}}", "fzs_fzf_pager_cmd"),
-        &global_config.fzs_fzf_pager_cmd,
-    ).replace(
-        &format!("{{{{ {} }}}}", "fzs_init_file"),
-        &pathbuf_to_string(&global_config.init_file,&global_config),
-    ).replace(
-        &format!("{{{{ {} }}}}", "fzs_fzf_base_preview"),
-        &global_config.fzs_fzf_base_preview,
-    );
-    if global_config.generated_file.is_absolute() {
-        contents = contents.replace(        &format!("{{{{ {} }}}}", "fzs_plugins_file"),
-        &pathbuf_to_string(&global_config.generated_file, &global_config))
+    // the default templates put these into double quotes, except for fzs_fzf_base_preview which is single quoted
+    let backend = global_config.backend();
+    let plugins_file = if global_config.generated_file.is_absolute() {
+        backend.double_quoted_path(&pathbuf_to_string(&global_config.generated_file, global_config))
+    } else {
+        String::new() // there is one per plugin
+    };
+    for (key, value) in [
+        ("fzs_name", global_config.fzs_name.clone()),
+        ("fzs_root_dir", backend.double_quoted_path(&pathbuf_to_string_basic(&global_config.root_dir))),
+        ("fzs_path_dir", backend.double_quoted_path(&pathbuf_to_string_basic(&global_config.path_dir))),
+        ("fzs_data_dir", backend.double_quoted_path(&pathbuf_to_string_basic(&global_config.data_dir))),
+        ("fzs_config_dir", backend.double_quoted_path(&pathbuf_to_string_basic(&global_config.config_dir))),
+        ("fzs_provides_file", backend.double_quoted_path(&pathbuf_to_string(&global_config.provides_file, global_config))),
+        ("fzs_fzf_dir_cmd", backend.double_quoted(&global_config.fzs_fzf_dir_cmd)),
+        ("fzs_fzf_pager_cmd", backend.double_quoted(&global_config.fzs_fzf_pager_cmd)),
+        ("fzs_init_file", backend.double_quoted_path(&pathbuf_to_string(&global_config.init_file,global_config))),
+        ("fzs_plugins_file", plugins_file),
+        ("fzs_fzf_base_preview", backend.single_quoted(&global_config.fzs_fzf_base_preview)),
+    ] {
+        ctx.insert(key, value.into());
     }
-    Ok(contents)
+    global_config.templates.render(source, minijinja::Value::from(ctx))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:#}", e)))
 }
 
 
 
 // todo: optimize
-fn templatize_plugins<'a>(
+// Renders the per-plugin files, or a single file if generated_file is absolute. Registers per-plugin files as sources.
+fn render_plugins<'a>(
     scanned_plugins: &'a mut HashMap<String, Plugin>,
     global_config: &'a GlobalConfig,
-) -> Result<(), std::io::Error> {
-    if global_config.generated_file.is_absolute() {
-        if let Err(e) = fs::write(&global_config.generated_file, &[]) {
-            eprintln!("Failed to clear the file: {}", e);
-        }
-    }
-    for plugin in scanned_plugins.values_mut() {
-        let mut replacements: HashMap<&str, Cow<'a, str>> = HashMap::new();
+) -> Result<Vec<(PathBuf, String)>, std::io::Error> {
+    let mut rendered = Vec::new();
+    let mut combined = String::new();
+
+    let mut sorted_plugins: Vec<&mut Plugin> = scanned_plugins.values_mut().collect();
+    sorted_plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    for plugin in sorted_plugins {
         let mut contents = "".to_string();
 
         if let Some(fn_table) = plugin.fn_table(global_config) {
-            contents = fs::read_to_string(&global_config.template_file)?;
-            replacements.insert("fn_table".into(), fn_table.into());
+            let mut replacements: BTreeMap<&str, minijinja::Value> = BTreeMap::new();
+            replacements.insert("fn_table", global_config.backend().double_quoted_path(&fn_table).into());
+            let field = accept::alias_field(
+                plugin.fn_table_template.as_deref().unwrap_or(&global_config.fn_table_template),
+            );
             replacements.insert(
-                "selector_name".into(),
-                templatize_simple(plugin, &global_config.selector_widget_template).into(),
+                "accept_args",
+                global_config.accept.with(&plugin.accept).fzf_args(&fn_table, field, global_config.backend()).into(),
             );
-            contents = templatize_contents(contents, global_config, &replacements)?;
+            replacements.insert(
+                "selector_name",
+                templatize_simple(plugin, &global_config.selector_widget_template, global_config).into(),
+            );
+            replacements.insert("pg_name", global_config.backend().double_quoted(&plugin.name).into());
+            replacements.insert("pg_alias", global_config.backend().double_quoted(plugin.get_alias_ref()).into());
+            replacements.insert("fns", plugin.fns_value(global_config));
+            let template = &global_config.template;
+            let location = Location::file(&plugin.name, &plugin.path);
+            contents = source_map(&templatize_simple(plugin, &global_config.selector_widget_template, global_config), &location, global_config);
+            contents.push('\n');
+            contents.push_str(&templatize_contents(template, global_config, replacements.clone())?);
+
+            // the same selector again, but picking by menu key instead of fuzzy matching
+            let keys: Vec<Option<&str>> = plugin
+                .ranked_fns(global_config)
+                .into_iter()
+                .map(|fun| fun.menu_key.as_deref())
+                .collect();
+            replacements.insert("accept_args", menu::fzf_args(&keys, global_config.backend()).into());
+            replacements.insert(
+                "selector_name",
+                templatize_simple(plugin, &global_config.menu_widget_template, global_config).into(),
+            );
+            contents.push('\n');
+            contents.push_str(&source_map(&templatize_simple(plugin, &global_config.menu_widget_template, global_config), &location, global_config));
+            contents.push('\n');
+            contents.push_str(&templatize_contents(template, global_config, replacements)?);
         }
 
         if let Some(extra_table) = plugin.extra_table(global_config) {
@@ -1880,22 +2690,43 @@ fn templatize_plugins<'a>(
             contents.push_str(&extra_table);
         }
         if !contents.is_empty() {
-            plugin.write_generated_file(&contents, global_config)?;
+            if global_config.generated_file.is_absolute() {
+                combined.push_str(&contents);
+            } else {
+                let file_path = plugin.generated_filepath(global_config);
+                plugin.sources.push(file_path.clone());
+                rendered.push((file_path, contents));
+            }
         }
     }
     if global_config.generated_file.is_absolute() {
-        compile_to_zwc(&global_config.generated_file)?;
+        rendered.push((global_config.generated_file.clone(), combined));
     }
-    Ok(())
+    Ok(rendered)
 }
 
-// passed iterators by value as they are "used up"
-fn write_envs<'a>(
-    scanned_plugins: impl Iterator<Item = &'a Plugin>,
-    global_config: &'a GlobalConfig,
+fn write_generated_file(file_path: &Path, contents: &str, global_config: &GlobalConfig) -> Result<(), io::Error> {
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?; // see Plugin::from_raw
+    }
+    fs::write(file_path, contents)?; // see source_map
+
+    debug!("Generated {:#?}", file_path);
+    debug!("Generated {:#?}", contents);
+    global_config.backend().compile(file_path)
+}
+
+fn templatize_plugins(
+    scanned_plugins: &mut HashMap<String, Plugin>,
+    global_config: &GlobalConfig,
+    quick: bool,
 ) -> Result<(), std::io::Error> {
-    for plugin in scanned_plugins {
-        plugin.write_env_file(global_config)?;
+    for (file_path, contents) in render_plugins(scanned_plugins, global_config)? {
+        if quick && is_up_to_date(&file_path, &contents, global_config) {
+            debug!("Unchanged {:#?}", file_path);
+            continue;
+        }
+        write_generated_file(&file_path, &contents, global_config)?;
     }
     Ok(())
 }
@@ -1930,6 +2761,8 @@ enum ScanningError {
     InvalidFn(String),
     #[display("fn {} is declared as a reference to a nonexistent plugin {}.", _0, _1)]
     MissingPlugin(String, String),
+    #[display("Plugins include each other: {}", _0)]
+    IncludeCycle(String),
     #[display("IO error: {}", _0)]
     Io(io::Error),
 }
@@ -1951,6 +2784,16 @@ enum FzsErrors {
     // ?: required for `Result<(), FzsErrors>` to implement `FromResidual<Result<Infallible, OsString>>
     #[display("OSString error: {:?}", _0)]
     OsString(OsString),
+    #[display("No action matching {}", _0)]
+    ActionNotFound(String),
+    #[display("No generated block at or above {}", _0)]
+    NoSourceMap(String),
+    #[display("Scanning found {} errors, nothing was written", _0)]
+    ScanErrors(usize),
+    #[display("Watch error: {}", _0)]
+    Watch(notify::Error),
+    #[display("Stopped receiving filesystem events")]
+    WatchStopped,
 }
 
 macro_rules! impl_from_error_enum {
@@ -1969,25 +2812,29 @@ impl_from_error_enum!(ScanningError, FzsErrors, FzsErrors::Scanning);
 impl_from_error_enum!(CreationError, FzsErrors, FzsErrors::Creation);
 impl_from_error_enum!(io::Error, FzsErrors, FzsErrors::Io);
 impl_from_error_enum!(OsString, FzsErrors, FzsErrors::OsString);
+impl_from_error_enum!(notify::Error, FzsErrors, FzsErrors::Watch);
 
 enum Scanned {
-    ScannedPlugin(InitialPlugin),
-    ScannedLinkedbin(InitialPlugin),
+    Plugin(InitialPlugin),
+    Linkedbin(InitialPlugin),
     None,
 }
 
-fn finalize_plugins<'a>(
+fn finalize_plugins(
     plugins: &mut HashMap<String, Plugin>,
-    global_config: &'a GlobalConfig,
-) -> Result<(), ScanningError> {
+    _global_config: &GlobalConfig,
+    diagnostics: &mut Diagnostics,
+) {
     let mut q: Vec<(String, String, String)> = Vec::new();
+    let mut missing: Vec<(String, String)> = Vec::new();
     for pg in plugins.values() {
         for fun in pg.fns.values() {
             if fun.flags.contains(&FnFlag::PG) {
                 if let Some(plugin) = plugins.get(&fun.name) {
                     q.push((pg.name.to_string(), fun.name.to_string(), plugin.get_alias()));  // Assuming alias is an Option and plugin is clonable
                 } else {
-                    return Err(ScanningError::MissingPlugin(pg.name.to_string(), fun.name.to_string()));
+                    diagnostics.error(&fun.location, ScanningError::MissingPlugin(pg.name.to_string(), fun.name.to_string()).to_string());
+                    missing.push((pg.name.to_string(), fun.name.to_string()));
                 }
             }
         }
@@ -1995,19 +2842,103 @@ fn finalize_plugins<'a>(
     for (pg, fun, alias) in q {
         plugins.get_mut(&pg).unwrap().fns.get_mut(&fun).unwrap().alias = Some(alias);
     }
-    Ok(())
+    for (pg, fun) in missing {
+        plugins.get_mut(&pg).unwrap().fns.remove(&fun);
+    }
+    for pg in plugins.values_mut() {
+        pg.assign_menu_keys(diagnostics);
+    }
 }
-fn main() -> Result<(), FzsErrors> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("warn")).init();
-    let config_dir = config_dir(None)?; // Use the config_dir function to get the directory path
-    let config_file_path = config_dir.join("config.toml"); // Append the config.toml file to the path
+
+// template_file is only written when missing, so one from an older fzs lacks what the default gained since
+fn check_template_file(global_config: &GlobalConfig, diagnostics: &mut Diagnostics) {
+    let source = &global_config.template;
+    let location = Location::file("", &global_config.template_file);
+    if !source.contains("._record") {
+        diagnostics.warn(
+            &location,
+            "template_file does not record selections, so `fzs rank` has nothing to rank by. Add the `\"{{ fzs_name }}\"._record \"$pg_name\" \"$cmd\"` line of the default template after the selection is read.",
+        );
+    }
+    if !global_config.template_accepts {
+        diagnostics.warn(
+            &location,
+            "template_file does not pass {{ accept_args }} to _base-select.wg, so the selectors use the default prefix auto-accept instead of the accept settings. Add it like the default template does.",
+        );
+    }
+}
+
+// Registers every bind the generated files will contain, see keybind::Registry
+fn check_binds(plugins: &HashMap<String, Plugin>, global_config: &GlobalConfig, diagnostics: &mut Diagnostics) {
+    let mut registry = keybind::Registry::default();
+    let settings = Location::plugin("[settings]");
+    for (binds, default, widget) in [
+        (&global_config.plugin_selector_binds, DEFAULT_PLUGIN_SELECTOR_BIND, "the plugin selector"),
+        (&global_config.all_fn_selector_binds, DEFAULT_ALL_FN_SELECTOR_BIND, "the all functions selector"),
+    ] {
+        for bind in binds {
+            registry.add(bind, keybind::Owner {
+                name: widget.to_string(),
+                location: settings.clone(),
+                overrides: global_config.overrides || bind == default,
+                keymap: global_config.keymap,
+            }, diagnostics);
+        }
+    }
+
+    let mut sorted_plugins: Vec<&Plugin> = plugins.values().collect();
+    sorted_plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    for pg in sorted_plugins {
+        for (binds, widget) in [
+            (&pg.binds, &global_config.selector_widget_template),
+            (&pg.menu_binds, &global_config.menu_widget_template),
+        ] {
+            for bind in binds {
+                registry.add(bind, keybind::Owner {
+                    name: templatize_simple(pg, widget, global_config),
+                    location: Location::plugin(&pg.name),
+                    overrides: pg.overrides,
+                    keymap: pg.keymap(global_config),
+                }, diagnostics);
+            }
+        }
+        // see extra_table, included actions are bound by their own plugin
+        for fun in pg.sorted_fns().into_iter().filter(|fun| fun.included_from.is_none()) {
+            for bind in &fun.binds {
+                registry.add(bind, keybind::Owner {
+                    name: fun.get_cmd(pg, global_config),
+                    location: fun.location.clone(),
+                    overrides: pg.overrides || fun.overrides,
+                    keymap: pg.keymap(global_config),
+                }, diagnostics);
+            }
+        }
+    }
+    registry.check(global_config.backend().default_binds(), diagnostics);
+}
+
+fn load_config(cli: &Cli) -> Result<(GlobalConfig, Vec<RawPlugin>), FzsErrors> {
+    let (config_dir, config_file_path) = match &cli.config {
+        Some(path) => {
+            let path = string_to_pathbuf(&path.to_string_lossy());
+            let parent = path
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .map_or_else(|| ".".to_string(), |p| p.to_string_lossy().into_owned());
+            (config_dir(Some(parent))?, path)
+        }
+        None => {
+            let config_dir = config_dir(None)?; // Use the config_dir function to get the directory path
+            let config_file_path = config_dir.join("config.toml"); // Append the config.toml file to the path
+            (config_dir, config_file_path)
+        }
+    };
 
     debug!("{:#?}", config_file_path);
 
-    let toml_content = fs::read_to_string(config_file_path).map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to read config.toml: {}", e),
+    let toml_content = fs::read_to_string(&config_file_path).map_err(|e| {
+        io::Error::other(
+            format!("Failed to read {}: {}", config_file_path.display(), e),
         )
     })?;
 
@@ -2015,16 +2946,35 @@ fn main() -> Result<(), FzsErrors> {
         toml::from_str(&toml_content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
     debug!("Raw Config {:#?}", &raw_config);
 
-    let raw_global_config = raw_config.settings;
-    let global_config = raw_global_config.to_global_config(config_dir)?;
+    let mut raw_global_config = raw_config.settings;
+    // command line flags take precedence over [settings]
+    if let Some(root_dir) = &cli.root_dir {
+        raw_global_config.root_dir = Some(root_dir.clone());
+    }
+    if let Some(data_dir) = &cli.data_dir {
+        raw_global_config.data_dir = Some(data_dir.clone());
+    }
+    let mut global_config = raw_global_config.to_global_config(config_dir)?;
+    global_config.config_file = config_file_path;
     debug!("Global Config {:#?}", &global_config);
 
-    let raw_plugins: Vec<RawPlugin> = raw_config.plugins;
+    Ok((global_config, raw_config.plugins))
+}
 
+// scan_for_plugins -> populate_plugins -> merge config -> finalize_plugins
+// Problems are collected along the way and reported together at the end, any error aborts. quiet only reports errors.
+fn scan_plugins(
+    global_config: &GlobalConfig,
+    raw_plugins: Vec<RawPlugin>,
+    cache: &mut ScanCache,
+    quiet: bool,
+) -> Result<HashMap<String, Plugin>, FzsErrors> {
+    let mut diagnostics = Diagnostics::default();
     let (scanned_initial_plugins, scanned_initial_linkedbins) = scan_for_plugins(
         &global_config.root_dir,
         &global_config.plugin_regex,
         &global_config.linkedbin_regex,
+        &mut diagnostics,
     )?;
 
     let mut provide_envs = HashMap::new(); //currently unimplemented
@@ -2035,60 +2985,575 @@ fn main() -> Result<(), FzsErrors> {
         scanned_initial_plugins.into_values(),
         &FnFlags::new(),
         &mut provide_envs,
-        &global_config,
+        global_config,
+        &mut diagnostics,
+        cache,
     )?;
     populate_plugins(
         &mut scanned_plugins,
         scanned_initial_linkedbins.into_iter(),
         &HashSet::from([FnFlag::NA]),
         &mut provide_envs,
-        &global_config,
+        global_config,
+        &mut diagnostics,
+        cache,
     )?;
 
     for rp in raw_plugins {
         match scanned_plugins.get_mut(&rp.name) {
             Some(plugin) => {
-                plugin.merge_from_raw(rp)?;
+                plugin.merge_from_raw(rp, &mut diagnostics)?;
             }
             None => {
-                warn!("Plugin {} not found in scanned plugins.", rp.name);
-                info!("Plugins from config haven't been implemented yet, try an empty folder");
-                // scanned_plugins.insert(rp.name, rp.into());
+                info!("Plugin {} not found in scanned plugins, creating it from config.", rp.name);
+                scanned_plugins.insert(rp.name.clone(), Plugin::from_raw(rp, global_config, &mut diagnostics)?);
             }
         }
     }
 
-    finalize_plugins(&mut scanned_plugins, &global_config)?;
+    resolve_includes(&mut scanned_plugins, global_config, &mut diagnostics);
+    finalize_plugins(&mut scanned_plugins, global_config, &mut diagnostics);
+    check_binds(&scanned_plugins, global_config, &mut diagnostics);
+    check_template_file(global_config, &mut diagnostics);
 
     debug!("Scanned Plugins {:#?}", scanned_plugins);
+    diagnostics.report(global_config, quiet);
+    match diagnostics.errors() {
+        0 => Ok(scanned_plugins),
+        errors => Err(FzsErrors::ScanErrors(errors)),
+    }
+}
 
-    templatize_plugins(&mut scanned_plugins, &global_config)?;
-    // write_envs(scanned_plugins.values(), &global_config)?;
-    symlink_fns(scanned_plugins.values(), &global_config)?;
-
+// quick only writes what changed and stays silent, so that it can run on shell startup
+fn build(
+    mut scanned_plugins: HashMap<String, Plugin>,
+    global_config: &GlobalConfig,
+    quick: bool,
+) -> Result<(), FzsErrors> {
+    global_config.create_dirs()?;
+    templatize_plugins(&mut scanned_plugins, global_config, quick)?;
+    if quick {
+        sync_symlinks(scanned_plugins.values(), global_config)?;
+    } else {
+        symlink_fns(scanned_plugins.values(), global_config)?;
+    }
 
     let home_dir = env::var("HOME").unwrap_or_else(|_| String::from("~"));
-    write_generated_init_file(&scanned_plugins, &global_config)?;
+    write_generated_init_file(&scanned_plugins, global_config, quick)?;
+    write_completion_file(&scanned_plugins, global_config, quick)?;
+    fs::write(stamp_path(global_config), "")?; // see render_stale_check
+    if quick {
+        return Ok(());
+    }
 
-    eprintln!("All operations complete! Run the following code to add the initialization step to your .zshrc if you haven't already.");
+    let rc_file = global_config.backend().rc_file();
+    eprintln!("All operations complete! Run the following code to add the initialization step to your {} if you haven't already.", rc_file.trim_start_matches("~/"));
 
     eprintln!(
-        "echo '. \"{}\"' >> ~/.zshrc",
+        "echo '. \"{}\"' >> {}",
         global_config
             .init_file
+            .clone()
             .into_os_string()
             .into_string()?
-            .replace(&home_dir, &"~")
+            .replace(&home_dir, "~"),
+        rc_file
     );
     if global_config.generated_file.is_absolute() {
         eprintln!(
-            "echo '. \"{}\"' >> ~/.zshrc",
+            "echo '. \"{}\"' >> {}",
             global_config
                 .generated_file
+                .clone()
                 .into_os_string()
                 .into_string()?
-                .replace(&home_dir, &"~")
+                .replace(&home_dir, "~"),
+            rc_file
+        );
+    }
+    Ok(())
+}
+
+// build --quick, also run by fzs watch on every change
+fn quick_build(global_config: &GlobalConfig, raw_plugins: Vec<RawPlugin>) -> Result<(), FzsErrors> {
+    let mut cache = ScanCache::load(global_config);
+    let scanned_plugins = scan_plugins(global_config, raw_plugins, &mut cache, true)?;
+    build(scanned_plugins, global_config, true)?;
+    cache.save(global_config)?;
+    Ok(())
+}
+
+fn sorted_plugins(plugins: &HashMap<String, Plugin>) -> Vec<&Plugin> {
+    let mut sorted: Vec<&Plugin> = plugins.values().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    sorted
+}
+
+fn sorted_flags(fun: &Fun) -> String {
+    let mut flags: Vec<String> = fun.flags.iter().map(|flag| flag.to_string()).collect();
+    flags.sort();
+    flags.join(",")
+}
+
+fn list_plugins(plugins: &HashMap<String, Plugin>, global_config: &GlobalConfig) {
+    for pg in sorted_plugins(plugins) {
+        match pg.get_desc_ref() {
+            Some(desc) => println!("{} ({}) - {}", pg.get_alias_ref(), pg.name, desc),
+            None => println!("{} ({})", pg.get_alias_ref(), pg.name),
+        }
+        for fun in pg.sorted_fns() {
+            println!(
+                "    {}\t{}\t{}",
+                fun.get_cmd(pg, global_config),
+                sorted_flags(fun),
+                fun.desc.as_deref().unwrap_or("")
+            );
+        }
+    }
+}
+
+fn show_ranking(plugins: &HashMap<String, Plugin>, global_config: &GlobalConfig) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs());
+    for pg in sorted_plugins(plugins) {
+        let ranked = pg.ranked_fns(global_config);
+        if ranked.is_empty() {
+            continue;
+        }
+        println!("{} ({}) - {:?}", pg.get_alias_ref(), pg.name, pg.sort.unwrap_or(global_config.sort));
+        for fun in ranked {
+            let cmd = fun.get_cmd(pg, global_config);
+            let usage = global_config.history.usage(&cmd);
+            let last = match usage.count {
+                0 => "never".to_string(),
+                _ => format!("{}h ago", now.saturating_sub(usage.last) / 3600),
+            };
+            println!(
+                "    {}{}\t{} uses\t{}\t{:.2}",
+                if fun.flags.contains(&FnFlag::TOP) { "(pinned) " } else { "" },
+                cmd,
+                usage.count,
+                last,
+                usage.score
+            );
+        }
+    }
+}
+
+fn check(plugins: &HashMap<String, Plugin>) {
+    let fn_count: usize = plugins.values().map(|pg| pg.fns.len()).sum();
+    eprintln!(
+        "Scanned {} plugins with {} actions, no problems found.",
+        plugins.len(),
+        fn_count
+    );
+}
+
+// zcompile writes its output next to the source
+fn zwc_path(path: &Path) -> PathBuf {
+    let mut zwc = path.as_os_str().to_owned();
+    zwc.push(".zwc");
+    PathBuf::from(zwc)
+}
+
+fn clean(global_config: &GlobalConfig) -> Result<(), FzsErrors> {
+    // path_dir is owned by fzs, see symlink_fns
+    for name in current_symlinks(global_config)?.keys() {
+        let path = global_config.path_dir.join(name);
+        fs::remove_file(&path)?;
+        debug!("Removed {}", path.display());
+    }
+
+    let mut generated = vec![
+        global_config.init_file.clone(),
+        completion_path(global_config),
+        ScanCache::path(global_config),
+        stamp_path(global_config),
+    ];
+    if global_config.generated_file.is_absolute() {
+        generated.push(global_config.generated_file.clone());
+    } else {
+        let (plugins, linkedbins) = scan_for_plugins(
+            &global_config.root_dir,
+            &global_config.plugin_regex,
+            &global_config.linkedbin_regex,
+            &mut Diagnostics::default(),
+        )?;
+        generated.extend(
+            plugins
+                .values()
+                .chain(linkedbins.iter())
+                .map(|ip| ip.path.join(&global_config.generated_file)),
         );
     }
+
+    for path in generated {
+        for file in [zwc_path(&path), path] {
+            if file.exists() {
+                fs::remove_file(&file)?;
+                info!("Removed {}", file.display());
+            }
+        }
+    }
+    let config_plugins = config_plugin_dir(global_config);
+    if config_plugins.exists() {
+        fs::remove_dir_all(&config_plugins)?;
+        info!("Removed {}", config_plugins.display());
+    }
+    eprintln!("Removed generated files and symlinks.");
     Ok(())
 }
+
+fn find_fns<'a>(
+    plugins: &'a HashMap<String, Plugin>,
+    global_config: &GlobalConfig,
+    query: &str,
+) -> Vec<(&'a Plugin, &'a Fun)> {
+    let mut matches = Vec::new();
+    for pg in sorted_plugins(plugins) {
+        for fun in pg.fns.values() {
+            if fun.get_cmd(pg, global_config) == query
+                || fun.alias.as_deref() == Some(query)
+                || format!("{}.{}", pg.name, fun.name) == query
+            {
+                matches.push((pg, fun));
+            }
+        }
+    }
+    matches
+}
+
+fn explain(
+    plugins: &HashMap<String, Plugin>,
+    global_config: &GlobalConfig,
+    query: &str,
+) -> Result<(), FzsErrors> {
+    let matches = find_fns(plugins, global_config, query);
+    if matches.is_empty() {
+        return Err(FzsErrors::ActionNotFound(query.to_string()));
+    }
+    for (pg, fun) in matches {
+        let cmd = fun.get_cmd(pg, global_config);
+        println!("{}", cmd);
+        println!("  plugin:  {} ({})", pg.name, pg.get_alias_ref());
+        println!("  name:    {}", fun.name);
+        println!("  alias:   {}", fun.alias.as_deref().unwrap_or("-"));
+        println!("  desc:    {}", fun.desc.as_deref().unwrap_or("-"));
+        println!("  flags:   {}", sorted_flags(fun));
+        println!("  binds:   {}", fun.binds.join(" "));
+        println!("  runs:    {}", fun.get_real_cmd(pg, global_config));
+        if let Some(bin) = &fun.bin {
+            println!("  bin:     {}", pathbuf_to_string(bin, global_config));
+            if !fun.flags.contains(&FnFlag::WG) && !fun.flags.contains(&FnFlag::PG) {
+                println!(
+                    "  symlink: {}",
+                    pathbuf_to_string(&global_config.path_dir.join(&cmd), global_config)
+                );
+            }
+        }
+        if let Some(fstring) = &fun.fstring {
+            println!("  env:     {}={} (while sourcing {})", fstring, cmd, pg.name);
+        }
+        match &fun.included_from {
+            Some(included_from) if fun.namespace.is_some() => println!("  generated by {}", included_from),
+            _ => {
+                let lines = pg.fun_lines(fun, global_config);
+                if !lines.is_empty() {
+                    println!("  generated:");
+                    for line in lines {
+                        println!("    {}", line);
+                    }
+                }
+            }
+        }
+        if !fun.provenance.is_empty() {
+            println!("  history:");
+            for change in fun.provenance.changes() {
+                println!("    {}", change.display(global_config));
+            }
+        }
+    }
+    Ok(())
+}
+
+// The reverse of source_map: finds the block a line of a generated file belongs to, and explains it if it is an action
+fn explain_generated_line(
+    plugins: &HashMap<String, Plugin>,
+    global_config: &GlobalConfig,
+    file: Option<PathBuf>,
+    line: usize,
+) -> Result<(), FzsErrors> {
+    let file = match file {
+        Some(file) => file,
+        None if global_config.generated_file.is_absolute() => global_config.generated_file.clone(),
+        None => {
+            return Err(FzsErrors::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "generated_file is written per plugin, pass the one to look into with --file",
+            )))
+        }
+    };
+    let contents = fs::read_to_string(&file)?;
+    let display = pathbuf_to_string(&file, global_config);
+    let Some((target, source)) = contents
+        .lines()
+        .take(line)
+        .collect::<Vec<&str>>()
+        .into_iter()
+        .rev()
+        .find_map(|l| l.strip_prefix(SOURCE_MAP_PREFIX))
+        .and_then(|map| map.split_once(" <- "))
+    else {
+        return Err(FzsErrors::NoSourceMap(format!("{}:{}", display, line)));
+    };
+    println!("{}:{} belongs to {}, from {}", display, line, target, source);
+    // selectors share their name with the PG actions listing them
+    if find_fns(plugins, global_config, target).iter().any(|(_, fun)| !fun.flags.contains(&FnFlag::PG)) {
+        println!();
+        explain(plugins, global_config, target)?;
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), FzsErrors> {
+    let mut cli = Cli::parse();
+    env_logger::Builder::from_env(Env::default().default_filter_or(cli.log_level())).init();
+
+    let (global_config, raw_plugins) = load_config(&cli)?;
+
+    // only build reads and refreshes the scan cache
+    let mut cache = ScanCache::new(&global_config);
+    match cli.command.take().unwrap_or(Commands::Build { dry_run: false, quick: false }) {
+        Commands::Build { dry_run: true, quick } => {
+            if quick {
+                cache = ScanCache::load(&global_config);
+            }
+            let mut scanned_plugins = scan_plugins(&global_config, raw_plugins, &mut cache, false)?;
+            Plan::new(&mut scanned_plugins, &global_config)?.print(&global_config);
+            Ok(())
+        }
+        Commands::Build { dry_run: false, quick: true } => quick_build(&global_config, raw_plugins),
+        Commands::Build { dry_run: false, quick: false } => {
+            let scanned_plugins = scan_plugins(&global_config, raw_plugins, &mut cache, false)?;
+            build(scanned_plugins, &global_config, false)?;
+            cache.save(&global_config)?;
+            // only once the build succeeded, a failed one writes nothing
+            History::compact(&global_config)?;
+            Ok(())
+        }
+        Commands::List { format } => {
+            let scanned_plugins = scan_plugins(&global_config, raw_plugins, &mut cache, false)?;
+            match format {
+                ListFormat::Text => list_plugins(&scanned_plugins, &global_config),
+                ListFormat::Json => println!("{}", export::json(&scanned_plugins, &global_config)?),
+                ListFormat::Tsv => print!("{}", export::tsv(&scanned_plugins, &global_config)),
+            }
+            Ok(())
+        }
+        Commands::Check => {
+            check(&scan_plugins(&global_config, raw_plugins, &mut cache, false)?);
+            Ok(())
+        }
+        Commands::Rank { show: false } => {
+            quick_build(&global_config, raw_plugins)?;
+            let dropped = History::compact(&global_config)?;
+            info!("Dropped {} selections that no longer count from the history", dropped);
+            Ok(())
+        }
+        Commands::Rank { show: true } => {
+            show_ranking(&scan_plugins(&global_config, raw_plugins, &mut cache, false)?, &global_config);
+            Ok(())
+        }
+        Commands::Clean => clean(&global_config),
+        Commands::Watch => watch::watch(&cli),
+        Commands::Explain { action: Some(action), .. } => explain(
+            &scan_plugins(&global_config, raw_plugins, &mut cache, false)?,
+            &global_config,
+            &action,
+        ),
+        Commands::Explain { action: None, generated_line, file } => explain_generated_line(
+            &scan_plugins(&global_config, raw_plugins, &mut cache, false)?,
+            &global_config,
+            file,
+            generated_line.unwrap_or(1),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A config.toml with settings and a root_dir holding files, in a temporary directory
+    fn fixture(settings: &str, files: &[(&str, &str)]) -> (tempfile::TempDir, GlobalConfig, Vec<RawPlugin>) {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = dir.path().join("root").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let config = dir.path().join("config.toml");
+        fs::write(
+            &config,
+            format!(
+                "[settings]\nroot_dir = \"{0}/root\"\npath_dir = \"{0}/bin\"\ndata_dir = \"{0}/data\"\n{1}\n",
+                dir.path().display(),
+                settings
+            ),
+        )
+        .unwrap();
+        let cli = Cli::parse_from(["fzs", "--config", config.to_str().unwrap()]);
+        let (global_config, raw_plugins) = load_config(&cli).unwrap();
+        (dir, global_config, raw_plugins)
+    }
+
+    #[test]
+    fn bash_sources_are_sourced_with_bash() {
+        let files = [("tools_t_select/greet.bash", "# : desc=greets\n$greet() {\n  echo hi\n}\n")];
+        for (shell, sourced) in [("bash", true), ("zsh", false)] {
+            let (_dir, global_config, raw_plugins) = fixture(&format!("shell = \"{}\"", shell), &files);
+            let plugins = scan_plugins(&global_config, raw_plugins, &mut ScanCache::new(&global_config), false).unwrap();
+            assert_eq!(plugins["tools"].fns.contains_key("greet"), sourced, "{}", shell);
+            let init = render_init_file(&plugins, &global_config).unwrap();
+            assert_eq!(init.matches("greet.bash").count(), usize::from(sourced), "{}", shell);
+        }
+    }
+
+    // The plugins of config with their includes resolved, and the diagnostics of resolving them
+    fn resolved(config: &str) -> (HashMap<String, Plugin>, Vec<diagnostics::Diagnostic>) {
+        let (_dir, global_config, raw_plugins) = fixture(config, &[]);
+        let mut diagnostics = Diagnostics::default();
+        let mut plugins = raw_plugins
+            .into_iter()
+            .map(|rp| (rp.name.clone(), Plugin::from_raw(rp, &global_config, &mut diagnostics).unwrap()))
+            .collect();
+        resolve_includes(&mut plugins, &global_config, &mut diagnostics);
+        (plugins, diagnostics.since(0))
+    }
+
+    fn names(pg: &Plugin) -> Vec<&str> {
+        pg.sorted_fns().iter().map(|fun| fun.name.as_str()).collect()
+    }
+
+    #[test]
+    fn includes_resolve_transitively() {
+        let (plugins, diagnostics) = resolved(
+            r#"
+[[plugins]]
+name = "a"
+fns = [ { name = "b", flags = [ "PGI" ] }, { name = "one", cmd = "echo 1" } ]
+[[plugins]]
+name = "b"
+alias = "bb"
+fns = [ { name = "c", flags = [ "PGI" ] }, { name = "two", cmd = "echo 2" } ]
+[[plugins]]
+name = "c"
+alias = "cc"
+fns = [ { name = "three", cmd = "echo 3" } ]
+"#,
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        assert_eq!(names(&plugins["a"]), ["one", "three", "two"]);
+        assert_eq!(names(&plugins["b"]), ["three", "two"]);
+        let three = &plugins["a"].fns["three"];
+        assert_eq!(three.namespace.as_deref(), Some("cc"));
+        assert_eq!(three.included_from.as_deref(), Some("c"));
+        assert_eq!(plugins["a"].fns["two"].namespace.as_deref(), Some("bb"));
+    }
+
+    #[test]
+    fn include_cycles_are_reported_and_dropped() {
+        let (plugins, diagnostics) = resolved(
+            r#"
+[[plugins]]
+name = "a"
+fns = [ { name = "b", flags = [ "PGI" ] }, { name = "one", cmd = "echo 1" } ]
+[[plugins]]
+name = "b"
+fns = [ { name = "a", flags = [ "PGI" ] }, { name = "two", cmd = "echo 2" } ]
+"#,
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("a -> b -> a"), "{}", diagnostics[0].message);
+        // b's include of a closed the cycle, a still includes b
+        assert_eq!(names(&plugins["b"]), ["two"]);
+        assert_eq!(names(&plugins["a"]), ["one", "two"]);
+    }
+
+    #[test]
+    fn rename_keeps_the_namespace_of_transitive_includes() {
+        let (plugins, diagnostics) = resolved(
+            r#"
+[[plugins]]
+name = "a"
+include_namespace = "rename"
+fns = [ { name = "b", flags = [ "PGI" ] } ]
+[[plugins]]
+name = "b"
+alias = "bb"
+fns = [ { name = "c", flags = [ "PGI" ] }, { name = "two", cmd = "echo 2" } ]
+[[plugins]]
+name = "c"
+alias = "cc"
+fns = [ { name = "three", cmd = "echo 3" } ]
+"#,
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        // b kept c's namespace, so renaming into a only applies to b's own actions
+        assert_eq!(plugins["a"].fns["two"].namespace, None);
+        assert_eq!(plugins["a"].fns["three"].namespace.as_deref(), Some("cc"));
+    }
+
+    #[test]
+    fn file_name_modifiers_override_the_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("open");
+        fs::write(&path, "#!/bin/sh\n# fzs: WG alias=h desc=from the header\n# fzs: key=k alias=hh\n").unwrap();
+        let location = Location::plugin("files");
+        let mut diagnostics = Diagnostics::default();
+        let (header, header_location) = read_header(&path, &location, &mut diagnostics);
+        assert_eq!(header_location, location.at_line(2));
+        let from_file_name = process_ext(Some("alias=f binds=^[f"), 6, &mut diagnostics, &location);
+        let (name, alias, desc, flags, binds, cmd, key, _) = merge_ext(header, from_file_name);
+        assert_eq!(diagnostics.since(0).len(), 0);
+        assert_eq!((name, cmd), (None, None));
+        assert_eq!(alias.as_deref(), Some("f"));
+        assert_eq!(desc.as_deref(), Some("from the header"));
+        assert_eq!(flags, FnFlags::from([FnFlag::WG]));
+        assert_eq!(binds, ["^[f"]);
+        assert_eq!(key.as_deref(), Some("k"));
+    }
+
+    #[test]
+    fn function_declarations() {
+        let source = "\
+# : desc=keyword
+function $one {
+}
+# : desc=parens
+$two() {
+}
+
+# : desc=spaced
+  $three () {
+}
+# : desc=literal
+function g.push() {
+}
+";
+        let (_dir, global_config, raw_plugins) = fixture("", &[("tools_t_select/tools.zshrc", source)]);
+        let plugins = scan_plugins(&global_config, raw_plugins, &mut ScanCache::new(&global_config), false).unwrap();
+        let fns = &plugins["tools"].fns;
+        for (name, desc) in [("one", "keyword"), ("two", "parens"), ("three", "spaced")] {
+            let fun = &fns[name];
+            assert_eq!(fun.desc.as_deref(), Some(desc), "{}", name);
+            assert!(!fun.flags.contains(&FnFlag::CMD), "{}", name);
+        }
+        // without a $ the function is called by its name, as with CMD
+        let push = fns.values().find(|fun| fun.desc.as_deref() == Some("literal")).unwrap();
+        assert!(push.flags.contains(&FnFlag::CMD));
+        assert_eq!(push.cmd.as_deref(), Some("g.push"));
+        assert_eq!(fns.len(), 4);
+    }
+}
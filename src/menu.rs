@@ -0,0 +1,170 @@
+use crate::accept;
+use crate::backend::Backend;
+use crate::quote;
+
+// What auto-assigned keys are made of, besides the letters of an action's label
+const ALPHABET: &str = "abcdefghijklmnopqrstuvwxyz0123456789";
+
+// An action of a plugin's menu: its alias and name, and the key= it asked for
+pub struct Entry<'a> {
+    pub alias: &'a str,
+    pub name: &'a str,
+    pub preferred: Option<&'a str>,
+}
+
+pub struct Assignment {
+    pub keys: Vec<Option<String>>, // by entry, None if every candidate was taken
+    pub conflicts: Vec<(usize, usize)>, // an entry whose preferred key clashed, and the entry holding it
+}
+
+// The entry whose key is key, starts with key or is the start of key. Keys have to be prefix free, as the menu runs an
+// action as soon as its key is typed.
+fn clash(key: &str, keys: &[Option<String>]) -> Option<usize> {
+    keys.iter().position(|other| {
+        other
+            .as_deref()
+            .is_some_and(|other| other.starts_with(key) || key.starts_with(other))
+    })
+}
+
+// The letters of the alias and then the name, then pairs of them in order, then the first letter followed by any
+// letter, then any pair
+fn candidates(entry: &Entry) -> Vec<String> {
+    let mut letters: Vec<char> = Vec::new();
+    for c in entry.alias.chars().chain(entry.name.chars()).filter(char::is_ascii_alphanumeric) {
+        let c = c.to_ascii_lowercase();
+        if !letters.contains(&c) {
+            letters.push(c);
+        }
+    }
+    let alphabet: Vec<char> = ALPHABET.chars().collect();
+    let mut candidates: Vec<String> = letters.iter().map(char::to_string).collect();
+    for (i, first) in letters.iter().enumerate() {
+        for second in &letters[i + 1..] {
+            candidates.push(format!("{}{}", first, second));
+        }
+    }
+    for first in letters.first().into_iter().chain(&alphabet) {
+        for second in &alphabet {
+            candidates.push(format!("{}{}", first, second));
+        }
+    }
+    candidates
+}
+
+// Gives every entry the shortest free key it can get. Preferred keys are honoured first, in entry order, then the
+// remaining entries take turns, so that one entry only gets two letters once no entry can get one.
+pub fn assign(entries: &[Entry]) -> Assignment {
+    let mut keys: Vec<Option<String>> = vec![None; entries.len()];
+    let mut conflicts = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if let Some(preferred) = entry.preferred {
+            match clash(preferred, &keys) {
+                Some(holder) => conflicts.push((i, holder)),
+                None => keys[i] = Some(preferred.to_string()),
+            }
+        }
+    }
+
+    let candidates: Vec<Vec<String>> = entries.iter().map(candidates).collect();
+    for len in 1..=2 {
+        for i in 0..entries.len() {
+            if keys[i].is_some() {
+                continue;
+            }
+            keys[i] = candidates[i]
+                .iter()
+                .filter(|key| key.len() == len)
+                .find(|key| clash(key, &keys).is_none())
+                .cloned();
+        }
+    }
+    Assignment { keys, conflicts }
+}
+
+// The fzf arguments of a menu selector, whose rows have keys in this order. Typing stays in the query but filters
+// nothing: a full key moves to its row and accepts it, the start of a key waits for more and anything else starts over.
+pub fn fzf_args(keys: &[Option<&str>], backend: &dyn Backend) -> String {
+    let mut cases: Vec<String> = Vec::new();
+    let mut partial: Vec<String> = vec!["\"\"".to_string()];
+    for (row, key) in keys.iter().enumerate() {
+        let Some(key) = key else {
+            continue;
+        };
+        cases.push(format!(r#"{}) echo "pos({})+accept" ;;"#, quote::posix_word(key), row + 1));
+        for (i, _) in key.char_indices().skip(1) {
+            partial.push(quote::posix_word(&key[..i]));
+        }
+    }
+    partial.sort();
+    partial.dedup();
+    let script = format!(
+        r#"case "$1" in {} {}) ;; *) echo clear-query ;; esac"#,
+        cases.join(" "),
+        partial.join("|")
+    );
+    ["--disabled".to_string(), "--bind".to_string(), accept::transform("change", &script, "{q}")]
+        .iter()
+        .map(|arg| backend.word(arg))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry<'a>(alias: &'a str, name: &'a str, preferred: Option<&'a str>) -> Entry<'a> {
+        Entry { alias, name, preferred }
+    }
+
+    fn keys(assignment: &Assignment) -> Vec<&str> {
+        assignment.keys.iter().map(|key| key.as_deref().unwrap_or("")).collect()
+    }
+
+    #[test]
+    fn candidates_start_with_the_letters_of_alias_and_name() {
+        let candidates = candidates(&entry("gl", "Git-Log", None));
+        assert_eq!(candidates[..7], ["g", "l", "i", "t", "o", "gl", "gi"]);
+        assert!(candidates.iter().all(|key| key.chars().all(|c| ALPHABET.contains(c))));
+    }
+
+    #[test]
+    fn keys_are_prefix_free() {
+        let mut entries: Vec<Entry> = ["status", "stash", "switch", "show", "log", "pull", "push", "prune"]
+            .into_iter()
+            .map(|name| entry("", name, None))
+            .collect();
+        entries.extend((0..40).map(|_| entry("st", "stat", None)));
+        let assignment = assign(&entries);
+        let keys = keys(&assignment);
+        assert_eq!(keys[..9], ["s", "t", "w", "h", "l", "p", "u", "r", "a"]);
+        assert!(keys[9..].iter().all(|key| key.len() == 2));
+        for (i, key) in keys.iter().enumerate() {
+            for (j, other) in keys.iter().enumerate() {
+                assert!(i == j || !other.starts_with(key), "{} starts with {}", other, key);
+            }
+        }
+    }
+
+    #[test]
+    fn preferred_keys_win_in_entry_order() {
+        let entries = [
+            entry("", "open", None),
+            entry("", "copy", Some("o")),
+            entry("", "move", Some("o")),
+            entry("", "delete", Some("ox")),
+        ];
+        let assignment = assign(&entries);
+        assert_eq!(keys(&assignment), ["p", "o", "m", "d"]);
+        assert_eq!(assignment.conflicts, [(2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn two_letters_only_once_one_letter_keys_run_out() {
+        let assignment = assign(&[entry("", "a", None), entry("", "a", None), entry("", "b", None)]);
+        // the second a cannot get a letter of its own, but leaves b to the third entry
+        assert_eq!(keys(&assignment), ["a", "ca", "b"]);
+        assert!(assignment.conflicts.is_empty());
+    }
+}
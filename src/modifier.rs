@@ -0,0 +1,126 @@
+// Splitting of modifiers, the `field=value` and bare words of a `# :` line, an fzs: header or a file name, see
+// process_ext. Values may be quoted like in the shell, so that they can contain spaces.
+
+// The fields a modifier may set, a bare word is a list of flags
+pub const KEYS: [&str; 8] = ["name", "alias", "desc", "flags", "binds", "cmd", "key", "complete"];
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Token {
+    pub key: Option<String>,
+    pub value: String,
+    pub quoted: bool,
+    pub column: usize, // 1-based, in characters
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TokenError {
+    pub column: usize,
+    pub message: String,
+}
+
+// Words are separated by whitespace outside of quotes. Inside "..." a backslash escapes " and \, inside '...' it
+// escapes ' and \, anywhere else it is kept as is, so that binds like ^\ need no quoting.
+pub fn tokenize(s: &str) -> Result<Vec<Token>, TokenError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().enumerate().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut key = None;
+        let mut value = String::new();
+        let mut quoted = false;
+        while let Some(&(column, c)) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            chars.next();
+            match c {
+                '"' | '\'' => {
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some((_, '\\')) => match chars.peek() {
+                                Some(&(_, escaped)) if escaped == c || escaped == '\\' => {
+                                    value.push(escaped);
+                                    chars.next();
+                                }
+                                _ => value.push('\\'),
+                            },
+                            Some((_, closing)) if closing == c => break,
+                            Some((_, other)) => value.push(other),
+                            None => {
+                                return Err(TokenError {
+                                    column: column + 1,
+                                    message: format!("Unterminated {} quote", c),
+                                })
+                            }
+                        }
+                    }
+                }
+                '=' if key.is_none() && !quoted && is_key(&value) => key = Some(std::mem::take(&mut value)),
+                _ => value.push(c),
+            }
+        }
+        tokens.push(Token {
+            key,
+            value,
+            quoted,
+            column: start + 1,
+        });
+    }
+    Ok(tokens)
+}
+
+fn is_key(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // key=value, as a string to compare easily
+    fn words(s: &str) -> Vec<String> {
+        tokenize(s)
+            .unwrap()
+            .into_iter()
+            .map(|token| match token.key {
+                Some(key) => format!("{}={}", key, token.value),
+                None => token.value,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(words("WJR,PFN  binds=^[r desc=plain"), ["WJR,PFN", "binds=^[r", "desc=plain"]);
+    }
+
+    #[test]
+    fn quotes_keep_spaces_and_escapes() {
+        assert_eq!(
+            words(r#"desc="say \"hi\" \\ there" cmd='it\'s here' binds="ctrl-x ctrl-p""#),
+            [r#"desc=say "hi" \ there"#, "cmd=it's here", "binds=ctrl-x ctrl-p"]
+        );
+    }
+
+    #[test]
+    fn backslashes_stay_outside_quotes() {
+        assert_eq!(words(r"binds=^\ ^[\x"), [r"binds=^\", r"^[\x"]);
+    }
+
+    #[test]
+    fn columns() {
+        let tokens = tokenize("WG  alias=x").unwrap();
+        assert_eq!((tokens[0].column, tokens[1].column), (1, 5));
+        assert_eq!(
+            tokenize("alias=x desc=\"open").unwrap_err(),
+            TokenError {
+                column: 14,
+                message: "Unterminated \" quote".to_string()
+            }
+        );
+    }
+}
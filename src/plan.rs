@@ -0,0 +1,221 @@
+use crate::{
+    completion_path, current_symlinks, pathbuf_to_string, planned_symlinks, render_completion_file, render_init_file,
+    render_plugins, FzsErrors, GlobalConfig, Plugin,
+};
+use similar::TextDiff;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+// What `fzs build` would change, computed without touching the disk.
+pub struct Plan {
+    files: Vec<(PathBuf, Option<String>, String)>, // path, current contents, new contents
+    links_added: Vec<(String, PathBuf)>,
+    links_removed: Vec<(String, PathBuf)>,
+    links_retargeted: Vec<(String, PathBuf, PathBuf)>,
+    binds: LineChanges,
+    aliases: LineChanges,
+}
+
+#[derive(Default)]
+struct LineChanges {
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+impl LineChanges {
+    fn from_lines(old: &[&str], new: &[&str], prefix: &str) -> LineChanges {
+        let pick = |lines: &[&str]| -> BTreeSet<String> {
+            lines
+                .iter()
+                .map(|line| line.trim())
+                .filter(|line| line.starts_with(prefix))
+                .map(|line| line.to_string())
+                .collect()
+        };
+        let (old, new) = (pick(old), pick(new));
+        LineChanges {
+            added: new.difference(&old).cloned().collect(),
+            removed: old.difference(&new).cloned().collect(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl Plan {
+    // Mirrors build: render_plugins registers the generated per-plugin files as sources, so it runs before render_init_file.
+    pub fn new(
+        scanned_plugins: &mut HashMap<String, Plugin>,
+        global_config: &GlobalConfig,
+    ) -> Result<Plan, FzsErrors> {
+        let mut files = render_plugins(scanned_plugins, global_config)?;
+        files.push((
+            global_config.init_file.clone(),
+            render_init_file(scanned_plugins, global_config)?,
+        ));
+        if let Some(completion) = render_completion_file(scanned_plugins, global_config) {
+            files.push((completion_path(global_config), completion));
+        }
+        let files: Vec<(PathBuf, Option<String>, String)> = files
+            .into_iter()
+            .map(|(path, new)| {
+                let old = fs::read_to_string(&path).ok();
+                (path, old, new)
+            })
+            .collect();
+
+        let old_lines: Vec<&str> = files
+            .iter()
+            .filter_map(|(_, old, _)| old.as_deref())
+            .flat_map(str::lines)
+            .collect();
+        let new_lines: Vec<&str> = files.iter().flat_map(|(_, _, new)| new.lines()).collect();
+        let backend = global_config.backend();
+        let binds = LineChanges::from_lines(&old_lines, &new_lines, backend.bind_prefix());
+        let aliases = LineChanges::from_lines(&old_lines, &new_lines, backend.alias_prefix());
+
+        let current = current_symlinks(global_config)?;
+        let planned = planned_symlinks(scanned_plugins.values(), global_config);
+        let mut links_added = Vec::new();
+        let mut links_removed = Vec::new();
+        let mut links_retargeted = Vec::new();
+        for (name, target) in &planned {
+            match current.get(name) {
+                None => links_added.push((name.clone(), target.clone())),
+                Some(old) if old != target => {
+                    links_retargeted.push((name.clone(), old.clone(), target.clone()))
+                }
+                _ => (),
+            }
+        }
+        for (name, target) in &current {
+            if !planned.contains_key(name) {
+                links_removed.push((name.clone(), target.clone()));
+            }
+        }
+
+        Ok(Plan {
+            files: files
+                .into_iter()
+                .filter(|(_, old, new)| old.as_ref() != Some(new))
+                .collect(),
+            links_added,
+            links_removed,
+            links_retargeted,
+            binds,
+            aliases,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+            && self.links_added.is_empty()
+            && self.links_removed.is_empty()
+            && self.links_retargeted.is_empty()
+    }
+
+    pub fn print(&self, global_config: &GlobalConfig) {
+        if self.is_empty() {
+            println!("Nothing to do, everything is up to date.");
+            return;
+        }
+        let path_dir = pathbuf_to_string(&global_config.path_dir, global_config);
+
+        if !self.links_removed.is_empty() {
+            println!(
+                "Symlinks removed from {} (scripts calling these will break):",
+                path_dir
+            );
+            for (name, target) in &self.links_removed {
+                println!(
+                    "  - {} -> {}",
+                    name,
+                    pathbuf_to_string(target, global_config)
+                );
+            }
+        }
+        if !self.links_added.is_empty() {
+            println!("Symlinks added to {}:", path_dir);
+            for (name, target) in &self.links_added {
+                println!(
+                    "  + {} -> {}",
+                    name,
+                    pathbuf_to_string(target, global_config)
+                );
+            }
+        }
+        if !self.links_retargeted.is_empty() {
+            println!("Symlinks retargeted in {}:", path_dir);
+            for (name, old, new) in &self.links_retargeted {
+                println!(
+                    "  ~ {} -> {} (was {})",
+                    name,
+                    pathbuf_to_string(new, global_config),
+                    pathbuf_to_string(old, global_config)
+                );
+            }
+        }
+        for (title, changes) in [("Keybinds", &self.binds), ("Aliases", &self.aliases)] {
+            if !changes.is_empty() {
+                println!("{}:", title);
+                for line in &changes.removed {
+                    println!("  - {}", line);
+                }
+                for line in &changes.added {
+                    println!("  + {}", line);
+                }
+            }
+        }
+        if !self.files.is_empty() {
+            println!("Generated files:");
+            for (path, old, _) in &self.files {
+                let status = if old.is_some() { "~" } else { "+" };
+                println!("  {} {}", status, pathbuf_to_string(path, global_config));
+            }
+            for (path, old, new) in &self.files {
+                let name = path.to_string_lossy();
+                println!();
+                print!(
+                    "{}",
+                    TextDiff::from_lines(old.as_deref().unwrap_or(""), new)
+                        .unified_diff()
+                        .header(&name, &name)
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Shell;
+    use crate::keybind::Keymap;
+    use crate::FnFlag;
+
+    #[test]
+    fn binds_and_aliases_of_every_backend() {
+        for shell in [Shell::Zsh, Shell::Bash, Shell::Fish] {
+            let backend = shell.backend();
+            let bind = backend.bind("^[p", "v._select.wg", None);
+            let vi_bind = backend.bind("^[r", "v.rg", Some(Keymap::Viins));
+            let alias = backend.alias("d", "v.dlv");
+            // neither binds nor aliases
+            let mut other = backend.widget(&FnFlag::WJR, "v.rg", "v.rg");
+            other.push(backend.function("v.dlv", "command v.dlv"));
+            let other: Vec<&str> = other.iter().flat_map(|code| code.lines()).collect();
+
+            let old: Vec<&str> = other.iter().copied().chain([bind.as_str()]).collect();
+            let new: Vec<&str> = other.iter().copied().chain([vi_bind.as_str(), alias.as_str()]).collect();
+            let binds = LineChanges::from_lines(&old, &new, backend.bind_prefix());
+            let aliases = LineChanges::from_lines(&old, &new, backend.alias_prefix());
+            assert_eq!(binds.added, [vi_bind], "{:?}", shell);
+            assert_eq!(binds.removed, [bind], "{:?}", shell);
+            assert_eq!(aliases.added, [alias], "{:?}", shell);
+            assert!(aliases.removed.is_empty(), "{:?}", shell);
+        }
+    }
+}
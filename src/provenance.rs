@@ -0,0 +1,103 @@
+use crate::diagnostics::Location;
+use crate::GlobalConfig;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// The step of scanning that set a field of an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    Header, // a # fzs: comment near the top of an executable, see header::read
+    FileName, // the name of an executable: fn_regex and the modifiers after the first period
+    Directive, // a # : line in a source and the declaration following it
+    Config, // [[plugins]] in config.toml, see Fun::merge_from
+    Default, // a flag of the whole directory or source, i.e. NA in linkedbins
+    Check, // implied or removed by Fun::check, i.e. WG for an action with binds
+    Include, // copied into another plugin by PGI, see Plugin::map_includes
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Layer::Header => "fzs: header",
+            Layer::FileName => "file name",
+            Layer::Directive => "# : directive",
+            Layer::Config => "[[plugins]]",
+            Layer::Default => "default",
+            Layer::Check => "check",
+            Layer::Include => "include",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Set,
+    Add, // to a list, i.e. a flag
+    Unset,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub field: String,
+    pub op: Op,
+    pub value: String,
+    pub layer: Layer,
+    pub location: Location,
+}
+
+impl Change {
+    pub fn display<'a>(&'a self, global_config: &'a GlobalConfig) -> impl fmt::Display + 'a {
+        DisplayChange(self, global_config)
+    }
+}
+
+struct DisplayChange<'a>(&'a Change, &'a GlobalConfig);
+
+impl fmt::Display for DisplayChange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let change = self.0;
+        let assignment = match change.op {
+            Op::Set => format!("{} = {}", change.field, change.value),
+            Op::Add => format!("{} += {}", change.field, change.value),
+            Op::Unset => format!("{} unset", change.field),
+        };
+        write!(f, "{:<28} {} ({})", assignment, change.layer, change.location.display(self.1))
+    }
+}
+
+// Every change to the fields of an action, in the order scanning made them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    changes: Vec<Change>,
+}
+
+impl Provenance {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    // The values of field recorded in layer were really set in another one
+    pub fn reattribute(&mut self, field: &str, from: Layer, to: Layer, location: &Location) {
+        for change in self.changes.iter_mut().filter(|change| change.field == field && change.layer == from) {
+            change.layer = to;
+            change.location = location.clone();
+        }
+    }
+
+    pub fn record(&mut self, field: &str, op: Op, value: impl Into<String>, layer: Layer, location: &Location) {
+        self.changes.push(Change {
+            field: field.to_string(),
+            op,
+            value: value.into(),
+            layer,
+            location: location.clone(),
+        });
+    }
+}
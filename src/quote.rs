@@ -0,0 +1,226 @@
+// Escaping of values interpolated into generated shell code. Every function returns something that, once parsed by the
+// target shell in the given context, evaluates back to the input. See Backend::double_quoted and friends.
+
+// Variables the generated files refer to paths by, see pathbuf_to_string
+const PATH_VARS: [&str; 3] = ["HOME", "FZS_ROOT_DIR", "FZS_PATH_DIR"];
+
+// Characters that never need quoting in a zsh, bash or fish word
+fn is_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-.,/:@%+".contains(c)
+}
+
+// The contents of "..." in zsh and bash
+pub fn posix_double(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '"' | '$' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// The contents of '...' in zsh and bash, which cannot contain a ' at all
+pub fn posix_single(s: &str) -> String {
+    s.replace('\'', "'\\''")
+}
+
+pub fn posix_word(s: &str) -> String {
+    if !s.is_empty() && s.chars().all(is_safe) {
+        s.to_string()
+    } else {
+        format!("'{}'", posix_single(s))
+    }
+}
+
+// The contents of "..." in fish, where backticks are not special
+pub fn fish_double(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '"' | '$') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// The contents of '...' in fish, which allows \' and \\
+pub fn fish_single(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+pub fn fish_word(s: &str) -> String {
+    if !s.is_empty() && s.chars().all(is_safe) {
+        s.to_string()
+    } else {
+        format!("'{}'", fish_single(s))
+    }
+}
+
+// Escapes s with double, except for references to PATH_VARS, which are left to expand
+pub fn keeping_path_vars(s: &str, double: impl Fn(&str) -> String) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        let after = &rest[i + 1..];
+        let var = PATH_VARS.iter().find(|var| {
+            after.starts_with(*var)
+                && !after[var.len()..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        });
+        match var {
+            Some(var) => {
+                escaped.push_str(&double(&rest[..i]));
+                escaped.push('$');
+                escaped.push_str(var);
+                rest = &after[var.len()..];
+            }
+            None => {
+                escaped.push_str(&double(&rest[..=i]));
+                rest = after;
+            }
+        }
+    }
+    escaped.push_str(&double(rest));
+    escaped
+}
+
+// A cell of a selector table, where tabs and newlines delimit
+pub fn table_field(s: &str) -> String {
+    s.replace(['\t', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    const NASTY: [&str; 12] = [
+        "",
+        "plain",
+        "two words",
+        "say \"hi\"",
+        "it's",
+        "'",
+        "$HOME and ${HOME}",
+        "`touch /tmp/fzs_quote_test`",
+        "$(touch /tmp/fzs_quote_test)",
+        "back\\slash\\",
+        "multi\nline\n",
+        "tab\tand !history ~ * ? [a] {a,b} ; | & < > #",
+    ];
+
+    // The round trips through zsh and fish are ignored by default, since they fail where the shell is not installed.
+    // Run them with `cargo test -- --ignored`.
+    fn eval(shell: &str, script: &str) -> String {
+        let output = Command::new(shell)
+            .arg("-c")
+            .arg(script)
+            .output()
+            .unwrap_or_else(|e| panic!("cannot run {}: {}", shell, e));
+        assert!(
+            output.status.success(),
+            "{} failed on {:?}: {}",
+            shell,
+            script,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn round_trips(shell: &str, render: impl Fn(&str) -> String) {
+        for s in NASTY {
+            let script = render(s);
+            assert_eq!(eval(shell, &script), s, "{} evaluated {:?}", shell, script);
+        }
+    }
+
+    fn posix_round_trips(shell: &str) {
+        round_trips(shell, |s| format!("printf '%s' \"{}\"", posix_double(s)));
+        round_trips(shell, |s| format!("printf '%s' '{}'", posix_single(s)));
+        round_trips(shell, |s| format!("printf '%s' {}", posix_word(s)));
+    }
+
+    #[test]
+    fn bash_round_trip() {
+        posix_round_trips("bash");
+    }
+
+    #[test]
+    #[ignore = "needs zsh"]
+    fn zsh_round_trip() {
+        posix_round_trips("zsh");
+    }
+
+    #[test]
+    fn bash_alias_round_trip() {
+        round_trips("bash", |s| {
+            format!(
+                "alias x=\"{}\"; printf '%s' \"${{BASH_ALIASES[x]}}\"",
+                posix_double(s)
+            )
+        });
+    }
+
+    #[test]
+    #[ignore = "needs zsh"]
+    fn zsh_alias_round_trip() {
+        round_trips("zsh", |s| {
+            format!(
+                "alias x=\"{}\"; printf '%s' \"${{aliases[x]}}\"",
+                posix_double(s)
+            )
+        });
+    }
+
+    #[test]
+    #[ignore = "needs fish"]
+    fn fish_round_trip() {
+        round_trips("fish", |s| format!("printf '%s' \"{}\"", fish_double(s)));
+        round_trips("fish", |s| format!("printf '%s' '{}'", fish_single(s)));
+        round_trips("fish", |s| format!("printf '%s' {}", fish_word(s)));
+    }
+
+    #[test]
+    fn path_vars_expand() {
+        let path = "$FZS_ROOT_DIR/it's a \"$dir\"/$HOMEWORK/`x`";
+        let script = format!(
+            "FZS_ROOT_DIR=/root; printf '%s' \"{}\"",
+            keeping_path_vars(path, posix_double)
+        );
+        assert_eq!(eval("bash", &script), "/root/it's a \"$dir\"/$HOMEWORK/`x`");
+    }
+
+    #[test]
+    fn posix_escapes() {
+        assert_eq!(posix_double("say \"hi\" to $HOME `now` \\"), "say \\\"hi\\\" to \\$HOME \\`now\\` \\\\");
+        assert_eq!(posix_double("it's !#"), "it's !#");
+        assert_eq!(posix_single("it's"), "it'\\''s");
+        assert_eq!(posix_word("two words"), "'two words'");
+        assert_eq!(posix_word("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn fish_escapes() {
+        assert_eq!(fish_double("say \"hi\" to $HOME `now` \\"), "say \\\"hi\\\" to \\$HOME `now` \\\\");
+        assert_eq!(fish_single("it's a \\"), "it\\'s a \\\\");
+        assert_eq!(fish_word("two words"), "'two words'");
+        assert_eq!(fish_word("$(x)"), "'$(x)'");
+        assert_eq!(keeping_path_vars("$HOME/$HOMEWORK/\"$x\"", fish_double), "$HOME/\\$HOMEWORK/\\\"\\$x\\\"");
+    }
+
+    #[test]
+    fn safe_words_stay_unquoted() {
+        assert_eq!(posix_word("g.status"), "g.status");
+        assert_eq!(posix_word("^[p"), "'^[p'");
+        assert_eq!(posix_word("=ls"), "'=ls'");
+        assert_eq!(fish_word("it's"), "'it\\'s'");
+        assert_eq!(posix_word(""), "''");
+    }
+
+    #[test]
+    fn table_fields_stay_on_one_row() {
+        assert_eq!(table_field("a\tb\nc"), "a b c");
+    }
+}
@@ -0,0 +1,180 @@
+use crate::GlobalConfig;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A selection counts half as much after this long
+const HALF_LIFE_SECS: f64 = 7.0 * 24.0 * 60.0 * 60.0;
+// and does not count at all once it weighs less than this, after about ten half lives
+const MIN_WEIGHT: f64 = 0.001;
+
+// How selector rows are ordered, set with `sort` in [settings] or per plugin
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    #[default]
+    Frecency, // most used recently first, ties stay alphabetical
+    Alphabetical,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Usage {
+    pub count: usize,
+    pub last: u64,
+    pub score: f64,
+}
+
+// The selections the generated selectors appended to history.tsv, one `<epoch>\t<selector>\t<cmd>` line each. The selector is
+// the plugin name, or * for the all functions selector. Actions are identified by their cmd, which unlike the shown columns
+// is never padded or coloured.
+#[derive(Debug, Default)]
+pub struct History {
+    usage: HashMap<String, Usage>,
+}
+
+impl History {
+    pub fn path(global_config: &GlobalConfig) -> PathBuf {
+        global_config.data_dir.join("history.tsv")
+    }
+
+    // A missing history is empty, malformed lines are skipped
+    pub fn load(global_config: &GlobalConfig) -> History {
+        let Ok(contents) = fs::read_to_string(History::path(global_config)) else {
+            return History::default();
+        };
+        let now = now();
+        let mut usage: HashMap<String, Usage> = HashMap::new();
+        for line in contents.lines() {
+            let Some((time, cmd)) = parse(line) else {
+                log::debug!("Skipping history line {:?}", line);
+                continue;
+            };
+            let entry = usage.entry(cmd.to_string()).or_default();
+            entry.count += 1;
+            entry.last = entry.last.max(time);
+            entry.score += weight(now, time);
+        }
+        History { usage }
+    }
+
+    // Rewrites history.tsv without the malformed lines and the selections that decayed to nothing, so that it does not
+    // grow forever. Returns how many lines were dropped.
+    pub fn compact(global_config: &GlobalConfig) -> io::Result<usize> {
+        let path = History::path(global_config);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let now = now();
+        let kept: Vec<&str> = contents
+            .lines()
+            .filter(|line| parse(line).is_some_and(|(time, _)| weight(now, time) >= MIN_WEIGHT))
+            .collect();
+        let dropped = contents.lines().count() - kept.len();
+        if dropped > 0 {
+            let temp_path = path.with_extension("tsv.tmp");
+            fs::write(&temp_path, kept.iter().map(|line| format!("{}\n", line)).collect::<String>())?;
+            fs::rename(&temp_path, &path)?;
+        }
+        Ok(dropped)
+    }
+
+    pub fn usage(&self, cmd: &str) -> Usage {
+        self.usage.get(cmd).copied().unwrap_or_default()
+    }
+
+    // Pinned items first, then by score if sorting by frecency. The sort is stable, so items keep their order otherwise.
+    pub fn order<T>(
+        &self,
+        items: Vec<T>,
+        sort: Sort,
+        cmd: impl Fn(&T) -> String,
+        pinned: impl Fn(&T) -> bool,
+    ) -> Vec<T> {
+        let mut keyed: Vec<(bool, f64, T)> = items
+            .into_iter()
+            .map(|item| {
+                let score = match sort {
+                    Sort::Frecency => self.usage(&cmd(&item)).score,
+                    Sort::Alphabetical => 0.0,
+                };
+                (pinned(&item), score, item)
+            })
+            .collect();
+        keyed.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.total_cmp(&a.1)));
+        keyed.into_iter().map(|(_, _, item)| item).collect()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+// The time and cmd of a history line
+fn parse(line: &str) -> Option<(u64, &str)> {
+    let mut fields = line.splitn(3, '\t');
+    let time = fields.next()?.parse::<u64>().ok()?;
+    let _selector = fields.next()?;
+    Some((time, fields.next()?))
+}
+
+// What a selection at time counts for now
+fn weight(now: u64, time: u64) -> f64 {
+    0.5f64.powf(now.saturating_sub(time) as f64 / HALF_LIFE_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn history(scores: &[(&str, f64)]) -> History {
+        History {
+            usage: scores
+                .iter()
+                .map(|&(cmd, score)| (cmd.to_string(), Usage { count: 1, last: 0, score }))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn weight_halves_every_week() {
+        let now = 100 * DAY;
+        assert_eq!(weight(now, now), 1.0);
+        assert!((weight(now, now - 7 * DAY) - 0.5).abs() < 1e-9);
+        assert!((weight(now, now - 14 * DAY) - 0.25).abs() < 1e-9);
+        // a selection from the future counts fully
+        assert_eq!(weight(now, now + DAY), 1.0);
+        assert!(weight(now, now - 70 * DAY) < MIN_WEIGHT);
+    }
+
+    #[test]
+    fn parse_skips_malformed_lines() {
+        assert_eq!(parse("1700000000\tgit\tg.log"), Some((1700000000, "g.log")));
+        assert_eq!(parse("1700000000\t*\tg.log --all"), Some((1700000000, "g.log --all")));
+        assert_eq!(parse("yesterday\tgit\tg.log"), None);
+        assert_eq!(parse("1700000000\tgit"), None);
+    }
+
+    #[test]
+    fn order_by_score_ties_alphabetical_pinned_first() {
+        let history = history(&[("b", 2.0), ("d", 0.5), ("e", 2.0)]);
+        let items = vec!["a", "b", "c", "d", "e", "f"];
+        let pinned = |item: &&str| *item == "f";
+        assert_eq!(
+            history.order(items.clone(), Sort::Frecency, |item| item.to_string(), pinned),
+            ["f", "b", "e", "d", "a", "c"]
+        );
+        assert_eq!(
+            history.order(items, Sort::Alphabetical, |item| item.to_string(), pinned),
+            ["f", "a", "b", "c", "d", "e"]
+        );
+    }
+}
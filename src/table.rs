@@ -0,0 +1,89 @@
+use crate::FnFlag;
+use std::collections::HashSet;
+use unicode_width::UnicodeWidthStr;
+
+// Columns fzf does not show, see `--with-nth '1,4..'` in the init templates. The selectors read them back with
+// `IFS=$'\t' read`, so they are never padded or coloured.
+const HIDDEN_COLUMNS: [usize; 2] = [1, 2];
+
+// What an action's row is coloured by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Widget,
+    Plugin,
+    Background,
+}
+
+impl Category {
+    pub fn of(flags: &HashSet<FnFlag>) -> Option<Category> {
+        if flags.contains(&FnFlag::PG) || flags.contains(&FnFlag::PGI) {
+            Some(Category::Plugin)
+        } else if flags.contains(&FnFlag::PBG) {
+            Some(Category::Background)
+        } else if flags.iter().any(|flag| flag.is_widget()) {
+            Some(Category::Widget)
+        } else {
+            None
+        }
+    }
+
+    fn ansi(self) -> &'static str {
+        match self {
+            Category::Widget => "\x1b[34m",     // blue
+            Category::Plugin => "\x1b[35m",     // magenta
+            Category::Background => "\x1b[33m", // yellow
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+// A rendered row of fn_table_template, all_fn_table_template or the plugin table
+pub struct Row {
+    pub line: String,
+    pub category: Option<Category>,
+}
+
+// Pads the shown columns of each row to the widest cell of that column, so that fzf lines the descriptions up, and
+// optionally colours them by category. The last column of a row is left as is.
+pub fn render(rows: Vec<Row>, align: bool, colors: bool) -> String {
+    let rows: Vec<(Vec<&str>, Option<Category>)> = rows
+        .iter()
+        .map(|row| (row.line.split('\t').collect(), row.category))
+        .collect();
+    let mut widths: Vec<usize> = Vec::new();
+    if align {
+        for (cells, _) in &rows {
+            for (i, cell) in cells.iter().enumerate().take(cells.len().saturating_sub(1)) {
+                if i >= widths.len() {
+                    widths.resize(i + 1, 0);
+                }
+                widths[i] = widths[i].max(cell.width());
+            }
+        }
+    }
+
+    rows.iter()
+        .map(|(cells, category)| {
+            let last = cells.len().saturating_sub(1);
+            cells
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    if i == last || HIDDEN_COLUMNS.contains(&i) {
+                        return cell.to_string();
+                    }
+                    let padding = " ".repeat(widths.get(i).map_or(0, |width| width - cell.width()));
+                    match category {
+                        Some(category) if colors && !cell.is_empty() => {
+                            format!("{}{}{}{}", category.ansi(), cell, RESET, padding)
+                        }
+                        _ => format!("{}{}", cell, padding),
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join("\t")
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -0,0 +1,166 @@
+use crate::backend::Shell;
+use minijinja::value::{Enumerator, Object, ObjectRepr};
+use minijinja::{Environment, Error, ErrorKind, UndefinedBehavior, Value};
+use std::fmt;
+use std::sync::Arc;
+
+// Variables each kind of template gets, checked when the template is added so that a typo fails the config instead of ending up in the output
+pub const NAME_VARS: &[&str] = &["name", "alias", "desc"];
+pub const FN_VARS: &[&str] = &["name", "alias", "desc", "flags", "pg_name", "pg_alias"];
+pub const TABLE_VARS: &[&str] = &["name", "alias", "desc", "flags", "pg_name", "pg_alias", "cmds", "key"];
+pub const ALIAS_VARS: &[&str] = &["alias"];
+pub const FILE_VARS: &[&str] = &[
+    "fzs_name",
+    "fzs_root_dir",
+    "fzs_path_dir",
+    "fzs_data_dir",
+    "fzs_config_dir",
+    "fzs_provides_file",
+    "fzs_fzf_dir_cmd",
+    "fzs_fzf_pager_cmd",
+    "fzs_init_file",
+    "fzs_plugins_file",
+    "fzs_fzf_base_preview",
+];
+pub const SELECTOR_VARS: &[&str] = &["fn_table", "selector_name", "pg_name", "pg_alias", "fns", "accept_args"];
+pub const STALE_CHECK_VARS: &[&str] = &["fzs_name", "stamp_file", "config_file", "source_pattern", "on_stale"];
+// The default table rows, fields are separated by tabs and the selectors hide the second and third
+pub const FN_TABLE_TEMPLATE: &str = "{{ name }}\t{{ flags }}\t{{ cmds }}\t{{ key }}\t{{ alias }}\t{{ desc }}";
+pub const ALL_FN_TABLE_TEMPLATE: &str = "{{ pg_alias }}\t\t{{ cmds }}\t{{ name }}\t\t{{ alias }}\t\t{{ desc }}";
+pub const INIT_VARS: &[&str] = &["fn_table", "all_fn_table", "plugin_accept_args", "all_fn_accept_args", "base_accept_args"];
+
+// Functions minijinja provides without them being declared
+const BUILTIN_GLOBALS: &[&str] = &["range", "dict", "namespace", "debug"];
+
+// An action's flags: renders as ",WG,CMD," like the old tables did, but also works with `in` and `for`
+#[derive(Debug)]
+pub struct Flags(pub Vec<String>);
+
+impl Object for Flags {
+    fn repr(self: &Arc<Self>) -> ObjectRepr {
+        ObjectRepr::Seq
+    }
+
+    fn get_value(self: &Arc<Self>, key: &Value) -> Option<Value> {
+        self.0.get(key.as_usize()?).map(Value::from)
+    }
+
+    fn enumerate(self: &Arc<Self>) -> Enumerator {
+        Enumerator::Seq(self.0.len())
+    }
+
+    fn render(self: &Arc<Self>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ",{},", self.0.join(","))
+    }
+}
+
+// Pads to width with trailing spaces, counting chars
+fn pad(value: String, width: usize) -> String {
+    format!("{:<width$}", value, width = width)
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    env.add_filter("pad", pad);
+    env
+}
+
+fn check_vars(template: &minijinja::Template, vars: &[&str]) -> Result<(), Error> {
+    let mut unknown: Vec<String> = template
+        .undeclared_variables(false)
+        .into_iter()
+        .filter(|var| !vars.contains(&var.as_str()) && !BUILTIN_GLOBALS.contains(&var.as_str()))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    unknown.sort();
+    Err(Error::new(
+        ErrorKind::UndefinedError,
+        format!(
+            "unknown variable {} (available: {})",
+            unknown.join(", "),
+            vars.join(", ")
+        ),
+    ))
+}
+
+// Compiles source and checks it only uses vars, for templates that are not part of GlobalConfig like a plugin's fn_template
+pub fn check(source: &str, vars: &[&str]) -> Result<(), Error> {
+    let env = environment();
+    let template = env.template_from_str(source)?;
+    check_vars(&template, vars)
+}
+
+// The templates of a config, compiled once. They are keyed by their source, which is what templatize gets passed.
+#[derive(Debug)]
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    pub fn new(shell: Shell) -> Templates {
+        let mut env = environment();
+        env.add_filter("shell_quote", move |value: String| shell.backend().word(&value));
+        Templates { env }
+    }
+
+    pub fn add(&mut self, source: &str, vars: &[&str]) -> Result<(), Error> {
+        if self.env.get_template(source).is_ok() {
+            return Ok(());
+        }
+        self.env.add_template_owned(source.to_string(), source.to_string())?;
+        check_vars(&self.env.get_template(source)?, vars)
+    }
+
+    // Whether the added template source uses var
+    pub fn uses(&self, source: &str, var: &str) -> bool {
+        self.env
+            .get_template(source)
+            .is_ok_and(|template| template.undeclared_variables(false).contains(var))
+    }
+
+    // Templates that were not added (i.e. a plugin's own fn_template) are compiled on the fly
+    pub fn render(&self, source: &str, ctx: Value) -> Result<String, Error> {
+        match self.env.get_template(source) {
+            Ok(template) => template.render(ctx),
+            Err(_) => self.env.render_str(source, ctx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minijinja::context;
+
+    #[test]
+    fn unknown_variables_fail_with_the_available_ones() {
+        assert!(check("{{ name }} {{ alias | upper }}{% for i in range(2) %}{% endfor %}", NAME_VARS).is_ok());
+        let error = check("{{ nmae }} {{ pg_name }}", NAME_VARS).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UndefinedError);
+        assert!(
+            error.to_string().contains("unknown variable nmae, pg_name (available: name, alias, desc)"),
+            "{}",
+            error
+        );
+        let mut templates = Templates::new(Shell::Bash);
+        assert!(templates.add("{{ cmds }}", FN_VARS).is_err());
+        assert!(templates.add("{{ cmds }}", TABLE_VARS).is_ok());
+    }
+
+    #[test]
+    fn flags_render_as_a_list_and_work_with_in() {
+        let templates = Templates::new(Shell::Bash);
+        let flags = || Value::from_object(Flags(vec!["WG".to_string(), "CMD".to_string()]));
+        let render = |source: &str| templates.render(source, context! { flags => flags() }).unwrap();
+        assert_eq!(render("{{ flags }}"), ",WG,CMD,");
+        assert_eq!(render("{% if 'WG' in flags %}widget{% endif %}{% if 'W' in flags %} not a flag{% endif %}"), "widget");
+        assert_eq!(render("{% for flag in flags %}{{ flag | lower }} {% endfor %}"), "wg cmd ");
+        assert_eq!(render("{{ flags | length }}"), "2");
+        let empty = templates.render("{{ flags }}", context! { flags => Value::from_object(Flags(vec![])) });
+        assert_eq!(empty.unwrap(), ",,");
+    }
+}
@@ -0,0 +1,161 @@
+use crate::cli::Cli;
+use crate::{
+    load_config, pathbuf_to_string, pathbuf_to_string_basic, quick_build, zwc_path, FzsErrors,
+    GlobalConfig,
+};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+// Events closer together than this are handled by one rebuild, i.e. an editor writing a swap file and then the file
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+// What gets watched for a given config: root_dir recursively, and the directories containing config.toml and template_file,
+// since editors usually replace a file instead of writing to it.
+#[derive(PartialEq)]
+struct WatchSet {
+    root_dir: PathBuf,
+    files: BTreeSet<PathBuf>,
+    ignored: BTreeSet<PathBuf>, // written by the build itself
+    data_dir: PathBuf,
+    path_dir: PathBuf,
+}
+
+impl WatchSet {
+    fn new(global_config: &GlobalConfig) -> WatchSet {
+        let mut ignored = BTreeSet::new();
+        if !global_config.generated_file.is_absolute() {
+            // per plugin generated files are written into the plugin directories, see render_plugins
+            ignored.insert(global_config.generated_file.clone());
+            ignored.insert(zwc_path(&global_config.generated_file));
+        }
+        WatchSet {
+            root_dir: global_config.root_dir.clone(),
+            files: BTreeSet::from([
+                global_config.config_file.clone(),
+                global_config.template_file.clone(),
+            ]),
+            ignored,
+            data_dir: global_config.data_dir.clone(),
+            path_dir: global_config.path_dir.clone(),
+        }
+    }
+
+    fn dirs(&self) -> Vec<(PathBuf, RecursiveMode)> {
+        let parents: BTreeSet<&Path> = self
+            .files
+            .iter()
+            .filter_map(|file| file.parent())
+            .filter(|dir| !dir.starts_with(&self.root_dir))
+            .collect();
+        let mut dirs = vec![(self.root_dir.clone(), RecursiveMode::Recursive)];
+        dirs.extend(
+            parents
+                .into_iter()
+                .map(|dir| (dir.to_path_buf(), RecursiveMode::NonRecursive)),
+        );
+        dirs
+    }
+
+    fn is_relevant(&self, path: &Path) -> bool {
+        if self.files.contains(path) {
+            return true;
+        }
+        if !path.starts_with(&self.root_dir)
+            || path.starts_with(&self.data_dir)
+            || path.starts_with(&self.path_dir)
+        {
+            return false;
+        }
+        !path
+            .file_name()
+            .is_some_and(|name| self.ignored.contains(Path::new(name)))
+    }
+
+    fn watch(&self, watcher: &mut RecommendedWatcher) -> notify::Result<()> {
+        for (dir, mode) in self.dirs() {
+            watcher.watch(&dir, mode)?;
+        }
+        Ok(())
+    }
+
+    fn unwatch(&self, watcher: &mut RecommendedWatcher) {
+        for (dir, _) in self.dirs() {
+            let _ = watcher.unwatch(&dir);
+        }
+    }
+}
+
+// Blocks until something relevant changed, then returns the changed paths once no event arrived for DEBOUNCE
+fn wait_for_changes(
+    rx: &Receiver<notify::Result<Event>>,
+    watch_set: &WatchSet,
+) -> Result<BTreeSet<PathBuf>, FzsErrors> {
+    let mut changed = BTreeSet::new();
+    let mut timeout = None;
+    loop {
+        let event = match timeout {
+            None => rx.recv().map_err(|_| FzsErrors::WatchStopped)?,
+            Some(timeout) => match rx.recv_timeout(timeout) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => return Ok(changed),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(FzsErrors::WatchStopped),
+            },
+        };
+        let event = event?;
+        if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+            continue;
+        }
+        for path in event.paths {
+            if watch_set.is_relevant(&path) {
+                changed.insert(path);
+                timeout = Some(DEBOUNCE);
+            }
+        }
+    }
+}
+
+// Only fails if the config cannot be loaded, a failed build is just reported
+fn rebuild(cli: &Cli) -> Result<GlobalConfig, FzsErrors> {
+    let (global_config, raw_plugins) = load_config(cli)?;
+    match quick_build(&global_config, raw_plugins) {
+        Ok(()) => eprintln!("Rebuilt."),
+        Err(e) => eprintln!("Build failed: {}", e),
+    }
+    Ok(global_config)
+}
+
+// Reruns the pipeline like `fzs build --quick` on every change. If config.toml breaks, the previous config keeps being watched.
+pub fn watch(cli: &Cli) -> Result<(), FzsErrors> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+
+    let mut global_config = rebuild(cli)?;
+    let mut watch_set = WatchSet::new(&global_config);
+    watch_set.watch(&mut watcher)?;
+    eprintln!(
+        "Watching {} for changes, press Ctrl-C to stop.",
+        pathbuf_to_string_basic(&global_config.root_dir)
+    );
+
+    loop {
+        let changed = wait_for_changes(&rx, &watch_set)?;
+        for path in &changed {
+            eprintln!("Changed: {}", pathbuf_to_string(path, &global_config));
+        }
+        match rebuild(cli) {
+            Ok(new_global_config) => {
+                global_config = new_global_config;
+                let new_watch_set = WatchSet::new(&global_config);
+                if new_watch_set != watch_set {
+                    watch_set.unwatch(&mut watcher);
+                    watch_set = new_watch_set;
+                    watch_set.watch(&mut watcher)?;
+                }
+            }
+            Err(e) => eprintln!("Failed to load the config: {}", e),
+        }
+    }
+}